@@ -1,159 +1,453 @@
 #![allow(clippy::expect_fun_call)]
 
 mod card_pile;
-mod cards;
-mod effects;
+pub mod card_registry;
+pub mod cards;
+pub mod effects;
+pub mod history;
+pub mod kingdom;
 pub mod logs;
+pub mod observation;
 mod play_area;
-mod player_counters;
+pub mod player_counters;
 pub mod players;
+pub mod results;
 pub mod shuffler;
+pub mod snapshot;
 mod supply;
-
-use std::fmt::{Display, Formatter};
+mod trash;
+mod turn_state;
 
 use self::{
-    cards::{Card, CardNames, CardTypes, Cards},
+    card_registry::{CardRegistry, CardRegistryError},
+    cards::{Card, CardName, CardNames, CardTypes, Cards},
+    history::{GameHistory, MoveReason, StateChange},
+    kingdom::Kingdom,
     logs::{GameEvent, GameLog},
     play_area::PlayArea,
-    player_counters::PlayerCounters,
-    players::{Agent, BuyChoice},
+    players::{Agent2, AgentQuestion, TurnPhase, TurnStateStackEntry},
+    results::{GameEndReason, PlayerResult, PlayerResults},
     shuffler::Shuffler,
+    snapshot::{print_cards, GameSnapshot, PlayerSnapshot, SnapshotError},
     supply::Supply,
+    trash::Trash,
+    turn_state::{Opponent, TurnState},
 };
-use derive_more::Constructor;
 use itertools::Itertools;
 
+#[derive(Debug)]
+struct Player<'a> {
+    name: &'a str,
+    area: PlayArea<'a>,
+    agent: &'a mut dyn Agent2,
+    turns_taken: u8,
+}
+
 #[derive(Debug)]
 pub struct Game<'a> {
-    players: Vec<(&'a str, PlayArea<'a>, &'a mut dyn Agent)>,
+    players: Vec<Player<'a>>,
     supply: Supply,
+    trash: Trash,
     log: GameLog,
     turn_counter: u8,
     max_turns: u8,
+    /** Whose turn is next, so that a game can be restored partway through a round */
+    next_seat: usize,
+    /** Where the history starts, if we're keeping one */
+    history_start: Option<GameSnapshot>,
 }
 impl<'a> Game<'a> {
     pub fn new(log: GameLog) -> Self {
         Self {
             players: vec![],
             supply: Supply::new(),
+            trash: Trash::new(),
             log,
             turn_counter: 0,
             max_turns: 100,
+            next_seat: 0,
+            history_start: None,
         }
     }
 
     pub fn add_player(
         &mut self,
         name: &'a str,
-        agent: &'a mut dyn Agent,
+        agent: &'a mut dyn Agent2,
         shuffler: &'a dyn Shuffler<Card>,
     ) {
-        let area = PlayArea::new(shuffler);
-        self.players.push((name, area, agent));
+        let area = PlayArea::new(self.players.len(), shuffler);
+        self.players.push(Player {
+            name,
+            area,
+            agent,
+            turns_taken: 0,
+        });
     }
 
-    fn play_one_turn(&mut self) {
-        self.turn_counter += 1;
-        for (name, area, agent) in self.players.iter_mut() {
-            let _span = self.log.enter_turn(name, self.turn_counter);
+    pub fn snapshot(&self) -> GameSnapshot {
+        GameSnapshot {
+            turn_counter: self.turn_counter,
+            max_turns: self.max_turns,
+            next_seat: self.next_seat,
+            supply: self.supply.snapshot(),
+            trash: self.trash.inspect().map(|c| c.name).collect(),
+            players: self
+                .players
+                .iter()
+                .map(|p| PlayerSnapshot {
+                    name: p.name.to_owned(),
+                    turns_taken: p.turns_taken,
+                    zones: p.area.snapshot(),
+                    shuffler: p.area.shuffler_state(),
+                })
+                .collect(),
+        }
+    }
 
-            let mut player_counters = PlayerCounters::new_turn();
+    /** Sets up a game as it was when the snapshot was taken, ready for `resume_to_end`.
+     * Each seat needs an agent and a shuffler, eg a `RandomShuffler` made from the
+     * snapshot's `ShufflerState` to carry on exactly as the original game would have.
+     */
+    pub fn restore(
+        snapshot: &'a GameSnapshot,
+        registry: &CardRegistry,
+        log: GameLog,
+        seats: Vec<(&'a mut dyn Agent2, &'a dyn Shuffler<Card>)>,
+    ) -> Result<Self, SnapshotError> {
+        if seats.len() != snapshot.players.len() {
+            return Err(SnapshotError::WrongNumberOfPlayers {
+                expected: snapshot.players.len(),
+                given: seats.len(),
+            });
+        }
+        let mut game = Game::new(log);
+        game.turn_counter = snapshot.turn_counter;
+        game.max_turns = snapshot.max_turns;
+        game.next_seat = snapshot.next_seat;
+        for (name, cards) in &snapshot.supply {
+            game.supply.add_named(*name, print_cards(registry, cards)?);
+        }
+        for card in print_cards(registry, &snapshot.trash)? {
+            game.trash.add(card);
+        }
+        for (seat, (player, (agent, shuffler))) in snapshot.players.iter().zip(seats).enumerate() {
+            game.players.push(Player {
+                name: &player.name,
+                area: PlayArea::restore(seat, shuffler, &player.zones, registry)?,
+                agent,
+                turns_taken: player.turns_taken,
+            });
+        }
+        Ok(game)
+    }
 
-            {
-                let _span = self.log.enter_action_phase();
-                while player_counters.actions > 0 {
-                    let playable_cards = area
-                        .inspect_hand()
-                        .filter(|c| c.get_types().any(|t| t == CardTypes::ACTION))
-                        .map(|c| c.name)
-                        .collect_vec();
-                    let action_choice = agent.action_phase(&playable_cards);
-                    match action_choice {
-                        players::PlayChoice::Play(card) => {
-                            area.play_card(card, &mut player_counters, &self.log);
-                            self.log
-                                .record(GameEvent::CardPlayed(card, player_counters.clone()));
-                            player_counters.actions -= 1;
-                        }
-                        players::PlayChoice::None => {
-                            break;
-                        }
-                    }
-                }
+    /** Every sort of card in the game so far. Cards only ever come from the supply
+     * or the starting decks, so from the start of a game on, this can `restore`
+     * any snapshot of it
+     */
+    pub fn card_registry(&self) -> CardRegistry {
+        let mut registry = CardRegistry::new();
+        let player_cards = self.players.iter().flat_map(|p| {
+            p.area
+                .inspect_deck()
+                .chain(p.area.inspect_hand())
+                .chain(p.area.inspect_in_play())
+                .chain(p.area.inspect_discard())
+        });
+        let cards = self
+            .supply
+            .inspect()
+            .chain(self.trash.inspect())
+            .chain(player_cards);
+        for card in cards {
+            if registry.get(card.name).is_none() {
+                registry
+                    .add(card.clone())
+                    .expect("BUG: the card was just checked for");
             }
+        }
+        registry
+    }
 
-            {
-                let _span = self.log.enter_buy_phase();
+    /** Starts keeping track of everything that happens from now on, so that
+     * `history` can step back through it. Starting decks aren't tracked, so
+     * this needs to be called after `deal_starting_hands`.
+     */
+    pub fn start_history(&mut self) {
+        self.history_start = Some(self.snapshot());
+        self.log.start_recording_changes();
+    }
+
+    /** Everything that's happened since `start_history`, positioned at the current state */
+    pub fn history(&self) -> Option<GameHistory> {
+        let start = self.history_start.clone()?;
+        let changes = self
+            .log
+            .recorded_changes()
+            .expect("BUG: history started without recording changes");
+        let mut history = GameHistory::new(start, changes);
+        history.go_to_end();
+        Some(history)
+    }
+
+    /** Plays a few rounds (or fewer, if the game ends first), eg to get to
+     * an interesting position to take a snapshot of
+     */
+    pub fn play_rounds(&mut self, rounds: u8) {
+        for _ in 0..rounds {
+            if self.is_over() {
+                return;
+            }
+            self.play_one_turn();
+        }
+    }
+
+    /** Whether any of the conditions for ending the game have been met */
+    pub fn is_over(&self) -> bool {
+        Self::end_reason(
+            self.turn_counter,
+            self.max_turns,
+            &self.supply,
+            self.players.len(),
+        )
+        .is_some()
+    }
+
+    /** Plays the rest of the current round (which is usually all of it) */
+    fn play_one_turn(&mut self) {
+        loop {
+            self.take_turn(self.next_seat);
+
+            if self.next_seat == 0 || self.is_over() {
+                return;
+            }
+        }
+    }
+
+    fn take_turn(&mut self, player_index: usize) {
+        if player_index == 0 {
+            self.turn_counter += 1;
+        }
+        self.log.change(StateChange::TurnStarted {
+            player: player_index,
+            turn: self.turn_counter,
+        });
+        self.next_seat = (player_index + 1) % self.players.len();
+
+        // everyone else gets affected by attacks in turn order, starting from the player to our left
+        let (before, rest) = self.players.split_at_mut(player_index);
+        let (player, after) = rest
+            .split_first_mut()
+            .expect("BUG: player index out of range");
+        let opponents = after
+            .iter_mut()
+            .chain(before.iter_mut())
+            .map(|p| Opponent::new(p.name, &mut p.area, p.agent))
+            .collect();
 
-                // for now we just auto play all treasures
-                for c in area
+        let _span = self.log.enter_turn(player.name, self.turn_counter);
+        player.turns_taken += 1;
+
+        let area = &mut player.area;
+        let mut turn = TurnState::new(
+            &self.log,
+            player.agent,
+            &mut self.supply,
+            &mut self.trash,
+            opponents,
+            self.turn_counter,
+        );
+
+        area.start_turn(&mut turn);
+
+        {
+            let _span = self.log.enter_action_phase();
+            turn.push(TurnStateStackEntry::Phase(TurnPhase::Action));
+            while turn.counters.actions > 0 {
+                let playable_cards = area
                     .inspect_hand()
-                    .filter(|c| c.get_types().any(|t| t == CardTypes::TREASURE))
+                    .filter(|c| c.get_types().any(|t| t == CardTypes::ACTION))
                     .map(|c| c.name)
-                    .collect_vec()
-                {
-                    area.play_card(c, &mut player_counters, &self.log);
-                    self.log
-                        .record(GameEvent::CardPlayed(c, player_counters.clone()));
+                    .collect();
+                let action_choice =
+                    turn.request_choice(area, AgentQuestion::PlayAction(playable_cards));
+                match action_choice.first() {
+                    Some(&card) => {
+                        area.play_card(card, &mut turn);
+                        self.log
+                            .record(GameEvent::CardPlayed(card, turn.counters.clone()));
+                        turn.counters.actions -= 1;
+                    }
+                    None => {
+                        break;
+                    }
                 }
+            }
+            turn.pop();
+        }
+
+        {
+            let _span = self.log.enter_buy_phase();
+            turn.push(TurnStateStackEntry::Phase(TurnPhase::Buy));
+
+            // for now we just auto play all treasures
+            for c in area
+                .inspect_hand()
+                .filter(|c| c.get_types().any(|t| t == CardTypes::TREASURE))
+                .map(|c| c.name)
+                .collect_vec()
+            {
+                area.play_card(c, &mut turn);
+                self.log
+                    .record(GameEvent::CardPlayed(c, turn.counters.clone()));
+            }
 
-                let buyable_cards = self.supply.buyable_cards(player_counters.coins);
-                let buy_choice = agent.buy_phase(&buyable_cards.collect_vec());
-                match buy_choice {
-                    BuyChoice::Buy(card) => {
-                        let purchased = self.supply.take_one(card).expect(
+            while turn.counters.buys > 0 {
+                let buyable_cards = turn.supply.buyable_cards(turn.counters.coins).collect();
+                let buy_choice = turn.request_choice(area, AgentQuestion::BuyCard(buyable_cards));
+                match buy_choice.first() {
+                    Some(&card) => {
+                        let (from, purchased) = turn.supply.take_from_pile(card).expect(
                             "BUG: expected to be able to buy a card the supply told us was buyable",
                         );
-                        area.gain_card_to_discard_pile(purchased);
+                        turn.counters.coins -= purchased.coins_cost;
+                        turn.counters.buys -= 1;
+                        area.gain_card(purchased, from, MoveReason::Buy, false, &self.log);
                         self.log.record(GameEvent::CardBoughtGained(card));
                     }
-                    BuyChoice::None => {}
+                    None => {
+                        break;
+                    }
                 }
             }
+            turn.pop();
+        }
 
-            {
-                let _span = self.log.enter_cleanup();
-                area.discard_in_play();
-                area.discard_hand();
-                area.draw_hand(&self.log);
-            }
+        {
+            let _span = self.log.enter_cleanup();
+            turn.push(TurnStateStackEntry::Phase(TurnPhase::Cleanup));
+            area.discard_in_play(&self.log);
+            area.discard_hand(&self.log);
+            area.draw_hand(&self.log);
+            turn.pop();
+        }
+    }
 
-            if Self::has_ended(self.turn_counter, self.max_turns, &self.supply) {
-                return;
-            }
+    /** Victory piles are smaller in 2 player games */
+    fn victory_pile_size(&self) -> u8 {
+        if self.players.len() <= 2 {
+            8
+        } else {
+            12
         }
     }
 
+    /** The supply functions below size piles based on how many players there
+     * are, so all the players need to be added first.
+     */
     pub fn populate_basic_kingdom(&mut self) {
-        self.populate_supply(Cards::copper, 60);
+        let players = self.players.len();
+        let provinces = match players {
+            0..=2 => 8,
+            3 | 4 => 12,
+            5 => 15,
+            _ => 18,
+        };
+        let victory_cards = self.victory_pile_size();
+
+        // starting decks are printed separately, so don't come out of these piles, but
+        // big tables would leave no copper at all (which would count as an empty pile)
+        let coppers = 60usize.saturating_sub(7 * players).max(10);
+        self.populate_supply(Cards::copper, coppers as u8);
         self.populate_supply(Cards::silver, 40);
         self.populate_supply(Cards::gold, 30);
-        self.populate_supply(Cards::estate, 12);
-        self.populate_supply(Cards::duchy, 12);
-        self.populate_supply(Cards::province, 12);
+        self.populate_supply(Cards::estate, victory_cards);
+        self.populate_supply(Cards::duchy, victory_cards);
+        self.populate_supply(Cards::province, provinces);
+        // (solo games don't have official rules, but they're handy for testing)
+        let curses = 10 * players.saturating_sub(1).max(1);
+        self.populate_supply(Cards::curse, curses.try_into().unwrap_or(u8::MAX));
     }
 
     pub fn populate_prosperous_kingdom(&mut self) {
         self.populate_supply(Cards::platinum, 12);
-        self.populate_supply(Cards::colony, 12);
+        self.populate_supply(Cards::colony, self.victory_pile_size());
     }
 
-    pub fn populate_some_actions(&mut self) {
-        self.populate_supply(Cards::village, 10);
-        self.populate_supply(Cards::smithy, 10);
+    pub fn populate_kingdom(
+        &mut self,
+        registry: &CardRegistry,
+        kingdom: &Kingdom,
+    ) -> Result<(), CardRegistryError> {
+        for &name in &kingdom.cards {
+            self.populate_supply_of(registry, name)?;
+        }
+        Ok(())
+    }
+
+    /** Adds a kingdom pile of a card that might have come from a definition file:
+     * 10 cards, or the same as the other victory piles for victory cards
+     */
+    pub fn populate_supply_from(
+        &mut self,
+        registry: &CardRegistry,
+        name: &str,
+    ) -> Result<(), CardRegistryError> {
+        let name = registry.name(name)?;
+        self.populate_supply_of(registry, name)
+    }
+
+    fn populate_supply_of(
+        &mut self,
+        registry: &CardRegistry,
+        name: CardName,
+    ) -> Result<(), CardRegistryError> {
+        let card = registry
+            .get(name)
+            .ok_or_else(|| CardRegistryError::UnknownCard(name.as_str().to_owned()))?;
+        let printer = || card.clone();
+        let count = if printer().get_types().any(|t| t == CardTypes::VICTORY) {
+            self.victory_pile_size()
+        } else {
+            10
+        };
+        self.populate_supply(printer, count);
+        Ok(())
+    }
+
+    /** Adds a pile of exactly `count` cards, eg to set up the same supply as another game */
+    pub fn populate_pile(
+        &mut self,
+        registry: &CardRegistry,
+        name: CardName,
+        count: usize,
+    ) -> Result<(), CardRegistryError> {
+        let card = registry
+            .get(name)
+            .ok_or_else(|| CardRegistryError::UnknownCard(name.as_str().to_owned()))?;
+        self.supply.add((0..count).map(|_| card.clone()).collect());
+        Ok(())
+    }
+
+    /** Each supply pile's name and how many cards are left in it */
+    pub fn supply_piles(&self) -> Vec<(CardName, usize)> {
+        self.supply.piles().collect()
     }
 
     fn populate_supply(&mut self, printer: impl Fn() -> Card, count: u8) {
-        self.supply.add((0..count).map(|_| printer()).collect());
+        // named up front, since there might not be any cards to take the name from
+        self.supply
+            .add_named(printer().name, (0..count).map(|_| printer()).collect());
     }
 
-    fn deal_starting_hands(&mut self) {
-        for (_, area, _) in self.players.iter_mut() {
-            let mut coppers = self.supply.take_up_to_n(CardNames::COPPER, 7);
-            area.gain_cards_to_discard_pile(&mut coppers);
-            let mut estates = self.supply.take_up_to_n(CardNames::ESTATE, 3);
-            area.gain_cards_to_discard_pile(&mut estates);
+    pub fn deal_starting_hands(&mut self) {
+        for Player { area, .. } in self.players.iter_mut() {
+            let mut starting_deck = (0..7)
+                .map(|_| Cards::copper())
+                .chain((0..3).map(|_| Cards::estate()))
+                .collect();
+            area.gain_cards_to_discard_pile(&mut starting_deck);
 
             area.draw_hand(&self.log);
         }
@@ -162,68 +456,76 @@ impl<'a> Game<'a> {
     /** Annoyingly we can't just take &self here, since we want to call this from another method:
      * https://stackoverflow.com/a/32405737
      */
-    fn has_ended(turn_counter: u8, max_turns: u8, supply: &Supply) -> bool {
-        turn_counter >= max_turns
-            || supply
-                .empty_supply_piles()
-                // TODO: check for 3/4 empty supply piles
-                .any(|s| s == CardNames::PROVINCE || s == CardNames::COLONY)
+    fn end_reason(
+        turn_counter: u8,
+        max_turns: u8,
+        supply: &Supply,
+        player_count: usize,
+    ) -> Option<GameEndReason> {
+        let empty_piles = supply.empty_supply_piles().collect_vec();
+
+        if let Some(&pile) = empty_piles
+            .iter()
+            .find(|&&s| s == CardNames::PROVINCE || s == CardNames::COLONY)
+        {
+            return Some(GameEndReason::PileEmptied(pile));
+        }
+
+        let max_empty_piles = if player_count >= 5 { 4 } else { 3 };
+        if empty_piles.len() >= max_empty_piles {
+            return Some(GameEndReason::PilesEmptied(empty_piles.into()));
+        }
+
+        if turn_counter >= max_turns {
+            return Some(GameEndReason::TurnLimitReached(max_turns));
+        }
+
+        None
     }
 
-    fn collect_cards_and_get_results(&mut self) -> PlayerResults {
+    fn collect_cards_and_get_results(&mut self, end_reason: GameEndReason) -> PlayerResults<'_> {
         let mut results = vec![];
         // we could totally do this in a nondestructive way with references to
         // player cards rather than actually moving the card objects around,
         // but this way seems more fun
-        for (name, area, _) in self.players.iter_mut() {
-            let mut player_cards = area.take_all_cards();
+        for player in self.players.iter_mut() {
+            let mut player_cards = player.area.take_all_cards();
             player_cards.sort_by_key(|c| c.name);
             let score = Self::calculate_score(&player_cards);
-            results.push(PlayerResult::new(name, player_cards, score));
+            results.push(PlayerResult::new(
+                player.name,
+                player_cards,
+                score,
+                player.turns_taken,
+            ));
         }
-        PlayerResults(results)
+        PlayerResults::new(results, end_reason)
     }
 
-    fn calculate_score(player_cards: &[Card]) -> u8 {
-        player_cards.iter().map(|c| c.vp_value).sum()
+    fn calculate_score(player_cards: &[Card]) -> i16 {
+        player_cards.iter().map(|c| i16::from(c.vp_value)).sum()
     }
 
-    pub fn play_to_end(&mut self) -> PlayerResults {
+    pub fn play_to_end(&mut self) -> PlayerResults<'_> {
         self.deal_starting_hands();
-
-        while !Self::has_ended(self.turn_counter, self.max_turns, &self.supply) {
-            self.play_one_turn();
-        }
-        self.collect_cards_and_get_results()
-    }
-}
-
-#[derive(Debug, Constructor)]
-pub struct PlayerResult<'a> {
-    name: &'a str,
-    cards: Vec<Card>,
-    pub score: u8,
-}
-
-impl Display for PlayerResult<'_> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "{}: {} points", self.name, self.score)?;
-        for (name, cards) in &self.cards.iter().group_by(|c| c.name) {
-            writeln!(f, "  {:?} x{}", name, cards.count())?
-        }
-        Ok(())
+        self.resume_to_end()
     }
-}
 
-// create a newtype since we can't directly impl Display for Vec
-// https://github.com/apolitical/impl-display-for-vec
-pub struct PlayerResults<'a>(pub Vec<PlayerResult<'a>>);
-impl Display for PlayerResults<'_> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for result in &self.0 {
-            writeln!(f, "{}", result)?;
-        }
-        Ok(())
+    /** Like `play_to_end`, but for a game that's already started */
+    pub fn resume_to_end(&mut self) -> PlayerResults<'_> {
+        let end_reason = loop {
+            if let Some(reason) = Self::end_reason(
+                self.turn_counter,
+                self.max_turns,
+                &self.supply,
+                self.players.len(),
+            ) {
+                break reason;
+            }
+            self.play_one_turn();
+        };
+        self.log.record(GameEvent::GameEnded(end_reason.clone()));
+        self.collect_cards_and_get_results(end_reason)
     }
 }
 
@@ -234,10 +536,29 @@ mod tests {
     use super::*;
     use crate::game::{
         logs::tests::TestLog,
-        players::Agents,
-        shuffler::{NoShuffle, RandomShuffler},
+        observation::Observation,
+        players::{tests::busy_agent, Agents, ChoiceRequest, ChoiceResponse},
+        shuffler::{NoShuffle, PredestinedShuffler, RandomShuffler},
     };
 
+    /** Always tries to buy a Province, whether or not it's on offer */
+    #[derive(Debug, Default)]
+    struct OverlyAmbitious {
+        requests: Vec<ChoiceRequest>,
+        observations: Vec<Observation>,
+    }
+    impl Agent2 for OverlyAmbitious {
+        fn request_choice(
+            &mut self,
+            request: &ChoiceRequest,
+            observation: &Observation,
+        ) -> ChoiceResponse {
+            self.requests.push(request.clone());
+            self.observations.push(observation.clone());
+            ChoiceResponse::one(CardNames::PROVINCE)
+        }
+    }
+
     #[test]
     fn a_game_can_start_and_a_player_can_buy_something() {
         let testlog = Rc::new(TestLog::new());
@@ -275,10 +596,196 @@ mod tests {
         insta::assert_debug_snapshot!((game.players, game.supply));
     }
 
+    #[test]
+    fn extra_buys_let_a_player_buy_several_cards_in_one_turn() {
+        let testlog = Rc::new(TestLog::new());
+        let log = GameLog::new(testlog.clone());
+        let shuffler = RandomShuffler::new(1234);
+        let mut game = Game::new(log);
+        let mut player_1 = Agents::woodcutter_test();
+        game.add_player("Player 1", &mut player_1, &shuffler);
+        game.populate_supply(Cards::copper, 20);
+        game.populate_supply(Cards::estate, 3);
+        game.populate_supply(Cards::woodcutter, 10);
+        game.deal_starting_hands();
+        for _ in 0..4 {
+            game.play_one_turn();
+        }
+
+        insta::assert_snapshot!(testlog.dump());
+        insta::assert_debug_snapshot!((game.players, game.supply));
+    }
+
+    #[test]
+    fn attacks_affect_the_other_players() {
+        let testlog = Rc::new(TestLog::new());
+        let log = GameLog::new(testlog.clone());
+        let shuffler = RandomShuffler::new(1234);
+        let mut game = Game::new(log);
+        let mut player_1 = Agents::big_money_witch();
+        let mut player_2 = Agents::simple_big_money();
+        game.add_player("P1 [BMW]", &mut player_1, &shuffler);
+        game.add_player("P2 [SBM]", &mut player_2, &shuffler);
+        game.populate_basic_kingdom();
+        game.populate_supply(Cards::witch, 10);
+        game.deal_starting_hands();
+        for _ in 0..6 {
+            game.play_one_turn();
+        }
+
+        insta::assert_snapshot!(testlog.dump());
+    }
+
+    #[test]
+    fn cards_from_definition_files_can_be_bought_and_played() {
+        let mut registry = CardRegistry::with_builtin_cards();
+        registry
+            .load_str(include_str!("../cards/vanilla.ron"))
+            .unwrap();
+        let laboratory = registry.name("Laboratory").unwrap();
+        let testlog = Rc::new(TestLog::new());
+        let log = GameLog::new(testlog.clone());
+        let shuffler = RandomShuffler::new(1234);
+        let mut game = Game::new(log);
+        let mut player_1 = Agents::priorities(
+            vec![CardNames::PROVINCE, laboratory, CardNames::SILVER],
+            vec![laboratory],
+        );
+        game.add_player("Player 1", &mut player_1, &shuffler);
+        game.populate_basic_kingdom();
+        game.populate_supply_from(&registry, "Laboratory").unwrap();
+        game.deal_starting_hands();
+        for _ in 0..8 {
+            game.play_one_turn();
+        }
+
+        insta::assert_snapshot!(testlog.dump());
+    }
+
+    #[test]
+    fn invalid_choices_are_logged_and_ignored() {
+        let testlog = Rc::new(TestLog::new());
+        let log = GameLog::new(testlog.clone());
+        let shuffler = NoShuffle::new();
+        let mut game = Game::new(log);
+        let mut player_1 = OverlyAmbitious::default();
+        game.add_player("Player 1", &mut player_1, &shuffler);
+        game.populate_supply(Cards::copper, 10);
+        game.populate_supply(Cards::estate, 3);
+        game.deal_starting_hands();
+        game.play_one_turn();
+
+        insta::assert_snapshot!(testlog.dump());
+        insta::assert_debug_snapshot!(player_1.requests);
+    }
+
+    #[test]
+    fn agents_can_see_their_own_cards_and_the_supply() {
+        let log = GameLog::new(Rc::new(TestLog::new()));
+        let shuffler = NoShuffle::new();
+        let mut game = Game::new(log);
+        let mut player_1 = OverlyAmbitious::default();
+        game.add_player("Player 1", &mut player_1, &shuffler);
+        game.populate_supply(Cards::copper, 10);
+        game.populate_supply(Cards::estate, 3);
+        game.deal_starting_hands();
+        game.play_one_turn();
+
+        insta::assert_debug_snapshot!(player_1.observations);
+    }
+
+    #[test]
+    fn agents_can_see_what_everyone_else_has_in_play() {
+        let log = GameLog::new(Rc::new(TestLog::new()));
+        let shuffler = NoShuffle::new();
+        let mut game = Game::new(log);
+        let mut player_1 = OverlyAmbitious::default();
+        let mut player_2 = OverlyAmbitious::default();
+        game.add_player("Player 1", &mut player_1, &shuffler);
+        game.add_player("Player 2", &mut player_2, &shuffler);
+        game.populate_supply(Cards::copper, 10);
+        game.populate_supply(Cards::estate, 3);
+        game.deal_starting_hands();
+        game.play_one_turn();
+
+        let seen_by_player_2 = &player_2.observations[0];
+        assert_eq!(1, seen_by_player_2.seat);
+        let player_1 = &seen_by_player_2.players[0];
+        assert_eq!(0, player_1.seat);
+        assert_eq!(5, player_1.hand_size);
+        assert_eq!(3, player_1.score);
+        assert!(player_1.in_play.is_empty());
+    }
+
+    fn supply_with_empty_piles(piles: &[fn() -> Card]) -> Supply {
+        let mut supply = Supply::new();
+        supply.add(vec![Cards::province()]);
+        for printer in piles {
+            let card = printer();
+            let name = card.name;
+            supply.add(vec![card]);
+            supply.take_one(name);
+        }
+        supply
+    }
+
+    #[test]
+    fn game_continues_while_fewer_than_three_piles_are_empty() {
+        let supply = supply_with_empty_piles(&[Cards::estate, Cards::smithy]);
+
+        assert_eq!(None, Game::end_reason(1, 100, &supply, 2));
+    }
+
+    #[test]
+    fn three_empty_piles_end_a_game_with_up_to_four_players() {
+        let supply = supply_with_empty_piles(&[Cards::estate, Cards::smithy, Cards::village]);
+
+        assert_eq!(
+            Some(GameEndReason::PilesEmptied(Box::new([
+                CardNames::ESTATE,
+                CardNames::SMITHY,
+                CardNames::VILLAGE
+            ]))),
+            Game::end_reason(1, 100, &supply, 4)
+        );
+    }
+
+    #[test]
+    fn games_with_five_or_more_players_need_four_empty_piles() {
+        let supply = supply_with_empty_piles(&[Cards::estate, Cards::smithy, Cards::village]);
+        assert_eq!(None, Game::end_reason(1, 100, &supply, 5));
+
+        let supply =
+            supply_with_empty_piles(&[Cards::estate, Cards::smithy, Cards::village, Cards::chapel]);
+        assert!(matches!(
+            Game::end_reason(1, 100, &supply, 5),
+            Some(GameEndReason::PilesEmptied(_))
+        ));
+    }
+
+    #[test]
+    fn running_out_of_provinces_ends_the_game() {
+        let mut supply = supply_with_empty_piles(&[Cards::estate, Cards::smithy, Cards::village]);
+        supply.take_one(CardNames::PROVINCE);
+
+        assert_eq!(
+            Some(GameEndReason::PileEmptied(CardNames::PROVINCE)),
+            Game::end_reason(1, 100, &supply, 2)
+        );
+    }
+
+    #[test]
+    fn hitting_the_turn_limit_ends_the_game() {
+        let supply = supply_with_empty_piles(&[]);
+
+        assert_eq!(
+            Some(GameEndReason::TurnLimitReached(100)),
+            Game::end_reason(100, 100, &supply, 2)
+        );
+    }
+
     #[test]
     fn one_player_beats_another_buy_eventually_buying_enough_duchies() {
-        // TODO: print number of turns (per player) in results
-        // TODO: print the game end reason to the log
         let testlog = Rc::new(TestLog::new());
         let log = GameLog::new(testlog.clone());
         let shuffler = RandomShuffler::new(1234);
@@ -294,4 +801,246 @@ mod tests {
         insta::assert_snapshot!(testlog.dump());
         insta::assert_display_snapshot!(results);
     }
+
+    fn game_with_players(count: usize) -> Game<'static> {
+        let mut game = Game::new(GameLog::new(Rc::new(TestLog::new())));
+        let shuffler = Box::leak(Box::new(NoShuffle::new()));
+        for i in 0..count {
+            let name = Box::leak(format!("P{}", i + 1).into_boxed_str());
+            let agent = Box::leak(Box::new(Agents::simple_big_money()));
+            game.add_player(name, agent, shuffler);
+        }
+        game.populate_basic_kingdom();
+        game.populate_prosperous_kingdom();
+        game
+    }
+
+    fn pile_sizes(game: &Game) -> [usize; 7] {
+        [
+            CardNames::COPPER,
+            CardNames::ESTATE,
+            CardNames::DUCHY,
+            CardNames::PROVINCE,
+            CardNames::COLONY,
+            CardNames::CURSE,
+            CardNames::SILVER,
+        ]
+        .map(|name| game.supply.pile_size(name).unwrap())
+    }
+
+    #[test]
+    fn two_player_games_have_smaller_victory_piles() {
+        let game = game_with_players(2);
+        assert_eq!([46, 8, 8, 8, 8, 10, 40], pile_sizes(&game));
+    }
+
+    #[test]
+    fn three_player_games_have_twelve_of_each_victory_card() {
+        let game = game_with_players(3);
+        assert_eq!([39, 12, 12, 12, 12, 20, 40], pile_sizes(&game));
+    }
+
+    #[test]
+    fn four_player_games_have_twelve_of_each_victory_card() {
+        let game = game_with_players(4);
+        assert_eq!([32, 12, 12, 12, 12, 30, 40], pile_sizes(&game));
+    }
+
+    #[test]
+    fn six_player_games_have_extra_provinces_and_curses() {
+        let game = game_with_players(6);
+        assert_eq!([18, 12, 12, 18, 12, 50, 40], pile_sizes(&game));
+    }
+
+    #[test]
+    fn piles_stay_in_range_however_many_players_there_are() {
+        let game = game_with_players(9);
+        assert_eq!([10, 12, 12, 18, 12, 80, 40], pile_sizes(&game));
+    }
+
+    #[test]
+    fn six_player_games_can_be_played_to_the_end() {
+        let registry = CardRegistry::with_builtin_cards();
+        let kingdom = Kingdom::preset(&registry, "First Game").unwrap();
+        let mut agents = (0..6)
+            .map(|_| Agents::big_money_splash_smithys())
+            .collect_vec();
+        let shuffler = RandomShuffler::new(1234);
+        let mut game = Game::new(GameLog::new(Rc::new(TestLog::new())));
+        for (i, agent) in agents.iter_mut().enumerate() {
+            game.add_player(["P1", "P2", "P3", "P4", "P5", "P6"][i], agent, &shuffler);
+        }
+        game.populate_basic_kingdom();
+        game.populate_kingdom(&registry, &kingdom).unwrap();
+
+        let results = game.play_to_end();
+
+        assert!(results.players.iter().all(|p| p.turns_taken > 0));
+        assert!(!matches!(
+            results.end_reason,
+            GameEndReason::TurnLimitReached(_)
+        ));
+    }
+
+    #[test]
+    fn starting_decks_dont_come_out_of_the_supply() {
+        let mut game = game_with_players(3);
+        game.deal_starting_hands();
+
+        assert_eq!([39, 12, 12, 12, 12, 20, 40], pile_sizes(&game));
+        for player in game.players.iter_mut() {
+            let cards = player.area.take_all_cards();
+            assert_eq!(10, cards.len());
+            assert_eq!(
+                7,
+                cards.iter().filter(|c| c.name == CardNames::COPPER).count()
+            );
+        }
+    }
+
+    #[test]
+    fn victory_cards_from_definition_files_get_victory_sized_piles() {
+        let mut registry = CardRegistry::with_builtin_cards();
+        registry
+            .load_str(include_str!("../cards/vanilla.ron"))
+            .unwrap();
+        let mut game = game_with_players(2);
+        game.populate_supply_from(&registry, "Great Hall").unwrap();
+        game.populate_supply_from(&registry, "Laboratory").unwrap();
+
+        assert_eq!(
+            Some(8),
+            game.supply.pile_size(registry.name("Great Hall").unwrap())
+        );
+        assert_eq!(
+            Some(10),
+            game.supply.pile_size(registry.name("Laboratory").unwrap())
+        );
+    }
+
+    fn seeded_game<'a>(
+        shuffler: &'a dyn Shuffler<Card>,
+        p1: &'a mut dyn Agent2,
+        p2: &'a mut dyn Agent2,
+    ) -> Game<'a> {
+        let mut game = Game::new(GameLog::new(Rc::new(TestLog::new())));
+        game.add_player("P1", p1, shuffler);
+        game.add_player("P2", p2, shuffler);
+        game.populate_basic_kingdom();
+        game
+    }
+
+    fn describe(results: &PlayerResults) -> String {
+        format!("{:?} {:?}", results.players, results.end_reason)
+    }
+
+    #[test]
+    fn a_restored_snapshot_plays_out_the_same_as_the_original_game() {
+        let registry = CardRegistry::with_builtin_cards();
+        let original_shuffler = RandomShuffler::new(1234);
+        let (mut original_p1, mut original_p2) =
+            (Agents::simple_big_money(), Agents::greedy_for_duchies());
+        let mut original = seeded_game(&original_shuffler, &mut original_p1, &mut original_p2);
+        original.deal_starting_hands();
+        original.play_rounds(5);
+        let snapshot = original.snapshot();
+        let expected = describe(&original.resume_to_end());
+
+        let path = std::env::temp_dir().join(format!("snapshot-{}.json", std::process::id()));
+        snapshot.save(&path).unwrap();
+        let loaded = GameSnapshot::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(snapshot, loaded);
+
+        let shuffler = RandomShuffler::from_state(loaded.players[0].shuffler.clone().unwrap());
+        let (mut p1, mut p2) = (Agents::simple_big_money(), Agents::greedy_for_duchies());
+        let mut restored = Game::restore(
+            &loaded,
+            &registry,
+            GameLog::new(Rc::new(TestLog::new())),
+            vec![(&mut p1, &shuffler), (&mut p2, &shuffler)],
+        )
+        .unwrap();
+        assert_eq!(snapshot, restored.snapshot());
+
+        assert_eq!(expected, describe(&restored.resume_to_end()));
+    }
+
+    #[test]
+    fn snapshots_need_a_seat_for_every_player() {
+        let registry = CardRegistry::with_builtin_cards();
+        let shuffler = NoShuffle::new();
+        let (mut p1, mut p2) = (Agents::simple_big_money(), Agents::greedy_for_duchies());
+        let mut game = seeded_game(&shuffler, &mut p1, &mut p2);
+        game.deal_starting_hands();
+        let snapshot = game.snapshot();
+        let shuffler = NoShuffle::new();
+        let mut p1 = Agents::simple_big_money();
+
+        let result = Game::restore(
+            &snapshot,
+            &registry,
+            GameLog::new(Rc::new(TestLog::new())),
+            vec![(&mut p1, &shuffler)],
+        );
+
+        assert!(matches!(
+            result,
+            Err(SnapshotError::WrongNumberOfPlayers {
+                expected: 2,
+                given: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn every_preset_kingdom_can_be_played_to_the_end() {
+        let registry = CardRegistry::with_builtin_cards();
+        for (preset, _) in kingdom::PRESETS {
+            let kingdom = Kingdom::preset(&registry, preset).unwrap();
+            let (mut p1, mut p2) = (busy_agent(&kingdom), busy_agent(&kingdom));
+            let shuffler = RandomShuffler::new(1234);
+            let mut game = Game::new(GameLog::new(Rc::new(TestLog::new())));
+            game.add_player("P1", &mut p1, &shuffler);
+            game.add_player("P2", &mut p2, &shuffler);
+            game.populate_basic_kingdom();
+            game.populate_kingdom(&registry, &kingdom).unwrap();
+
+            let results = game.play_to_end();
+
+            assert!(results.players.iter().all(|p| p.turns_taken > 0));
+        }
+    }
+
+    #[test]
+    fn a_scripted_shuffle_decides_what_gets_drawn() {
+        let mut player_1 = Agents::simple_big_money();
+        let shuffler = PredestinedShuffler::new(
+            "Player 1",
+            [
+                [CardNames::COPPER; 5]
+                    .into_iter()
+                    .chain([CardNames::ESTATE; 3])
+                    .chain([CardNames::COPPER; 2])
+                    .collect(),
+                // by the end of the second turn we need to shuffle again
+                [CardNames::SILVER]
+                    .into_iter()
+                    .chain([CardNames::COPPER; 7])
+                    .chain([CardNames::ESTATE; 3])
+                    .collect(),
+            ],
+        );
+        let mut game = Game::new(GameLog::new(Rc::new(TestLog::new())));
+        game.add_player("Player 1", &mut player_1, &shuffler);
+        game.populate_supply(Cards::silver, 10);
+        game.deal_starting_hands();
+        game.play_one_turn();
+        game.play_one_turn();
+
+        // $5 on the first turn and $2 on the second
+        assert_eq!(Some(9), game.supply.pile_size(CardNames::SILVER));
+        assert_eq!(0, shuffler.remaining());
+    }
 }
@@ -46,9 +46,18 @@ impl CardPile {
         self.cards.drain(..).collect()
     }
 
+    /** From the bottom of the pile to the top */
+    pub fn iter(&self) -> impl Iterator<Item = &Card> + '_ {
+        self.cards.iter()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.cards.is_empty()
     }
+
+    pub fn len(&self) -> usize {
+        self.cards.len()
+    }
 }
 
 impl From<Vec<Card>> for CardPile {
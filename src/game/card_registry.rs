@@ -0,0 +1,259 @@
+use std::{
+    fmt::{Display, Formatter},
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use super::{
+    cards::{Card, CardName, CardTypes, Cards},
+    effects::{CardEffect, Reaction},
+};
+
+/** How a card gets written down in a card definition file, eg:
+ *
+ * ```ron
+ * (
+ *     name: "Laboratory",
+ *     cost: 5,
+ *     types: ["Action"],
+ *     effect: Sequence([DrawCards(2), AddActions(1)]),
+ * )
+ * ```
+ */
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CardDefinition {
+    name: String,
+    cost: u8,
+    #[serde(default)]
+    vp: i8,
+    types: Vec<String>,
+    #[serde(default)]
+    effect: CardEffect,
+    #[serde(default)]
+    reaction: Option<Reaction>,
+}
+
+#[derive(Debug)]
+pub enum CardRegistryError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    UnknownCardType { card: String, card_type: String },
+    DuplicateCard(CardName),
+    UnknownCard(String),
+}
+
+impl Display for CardRegistryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CardRegistryError::Io(e) => write!(f, "couldn't read card definitions: {}", e),
+            CardRegistryError::Parse(e) => write!(f, "couldn't parse card definitions: {}", e),
+            CardRegistryError::UnknownCardType { card, card_type } => {
+                write!(f, "{} has an unknown card type {:?}", card, card_type)
+            }
+            CardRegistryError::DuplicateCard(name) => {
+                write!(f, "{:?} is defined more than once", name)
+            }
+            CardRegistryError::UnknownCard(name) => write!(f, "there's no card called {:?}", name),
+        }
+    }
+}
+
+impl std::error::Error for CardRegistryError {}
+
+/** Every card we know how to make, looked up by name */
+#[derive(Debug, Default)]
+pub struct CardRegistry {
+    cards: Vec<Card>,
+}
+
+impl CardRegistry {
+    pub fn new() -> Self {
+        CardRegistry { cards: vec![] }
+    }
+
+    pub fn with_builtin_cards() -> Self {
+        let builtin = [
+            Cards::copper,
+            Cards::silver,
+            Cards::gold,
+            Cards::platinum,
+            Cards::estate,
+            Cards::duchy,
+            Cards::province,
+            Cards::colony,
+            Cards::curse,
+            Cards::village,
+            Cards::smithy,
+            Cards::chapel,
+            Cards::market,
+            Cards::festival,
+            Cards::woodcutter,
+            Cards::militia,
+            Cards::witch,
+            Cards::bandit,
+            Cards::moat,
+            Cards::throne_room,
+            Cards::wharf,
+            Cards::caravan,
+            Cards::merchant_ship,
+            Cards::fishing_village,
+            Cards::cellar,
+            Cards::workshop,
+            Cards::remodel,
+            Cards::mine,
+            Cards::merchant,
+        ];
+        CardRegistry {
+            cards: builtin.iter().map(|printer| printer()).collect(),
+        }
+    }
+
+    pub fn add(&mut self, card: Card) -> Result<(), CardRegistryError> {
+        if self.get(card.name).is_some() {
+            return Err(CardRegistryError::DuplicateCard(card.name));
+        }
+        self.cards.push(card);
+        Ok(())
+    }
+
+    /** Adds every card from a RON list of card definitions */
+    pub fn load_str(&mut self, definitions: &str) -> Result<(), CardRegistryError> {
+        let definitions: Vec<CardDefinition> =
+            ron::from_str(definitions).map_err(CardRegistryError::Parse)?;
+        for definition in definitions {
+            let types = definition
+                .types
+                .iter()
+                .map(|t| {
+                    CardTypes::from_name(t).ok_or_else(|| CardRegistryError::UnknownCardType {
+                        card: definition.name.clone(),
+                        card_type: t.clone(),
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            self.add(Card::new(
+                CardName::intern(&definition.name),
+                definition.cost,
+                definition.vp,
+                types,
+                definition.effect,
+                definition.reaction,
+            ))?;
+        }
+        Ok(())
+    }
+
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<(), CardRegistryError> {
+        let definitions = std::fs::read_to_string(path).map_err(CardRegistryError::Io)?;
+        self.load_str(&definitions)
+    }
+
+    /** Looks up a card by the name a person would write, eg "Throne Room" */
+    pub fn name(&self, name: &str) -> Result<CardName, CardRegistryError> {
+        self.cards
+            .iter()
+            .map(|c| c.name)
+            .find(|n| n.as_str() == name)
+            .ok_or_else(|| CardRegistryError::UnknownCard(name.to_owned()))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = CardName> + '_ {
+        self.cards.iter().map(|c| c.name)
+    }
+
+    pub fn get(&self, name: CardName) -> Option<&Card> {
+        self.cards.iter().find(|c| c.name == name)
+    }
+
+    /** Prints a fresh copy of a card */
+    pub fn make(&self, name: CardName) -> Option<Card> {
+        self.get(name).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::cards::CardNames;
+
+    use super::*;
+
+    #[test]
+    fn builtin_cards_can_be_found_by_name() {
+        let registry = CardRegistry::with_builtin_cards();
+
+        assert_eq!(
+            CardNames::THRONE_ROOM,
+            registry.name("Throne Room").unwrap()
+        );
+        let smithy = registry.make(CardNames::SMITHY).unwrap();
+        assert_eq!(4, smithy.coins_cost);
+        assert_eq!(CardEffect::DrawCards(3), smithy.effect);
+    }
+
+    #[test]
+    fn cards_can_be_defined_in_ron() {
+        let mut registry = CardRegistry::new();
+        registry
+            .load_str(
+                r#"[
+                    (
+                        name: "Great Hall",
+                        cost: 3,
+                        vp: 1,
+                        types: ["Action", "Victory"],
+                        effect: Sequence([DrawCards(1), AddActions(1)]),
+                    ),
+                    (
+                        name: "Harbinger Of Gold",
+                        cost: 6,
+                        types: ["Action"],
+                        effect: GainCard("Gold"),
+                    ),
+                ]"#,
+            )
+            .unwrap();
+
+        let great_hall = registry.make(registry.name("Great Hall").unwrap()).unwrap();
+        assert_eq!(1, great_hall.vp_value);
+        assert_eq!(
+            vec![CardTypes::ACTION, CardTypes::VICTORY],
+            great_hall.get_types().collect::<Vec<_>>()
+        );
+        let harbinger = registry.get(CardName::intern("Harbinger Of Gold")).unwrap();
+        assert_eq!(CardEffect::GainCard(CardNames::GOLD), harbinger.effect);
+    }
+
+    #[test]
+    fn the_shipped_definitions_load() {
+        let mut registry = CardRegistry::with_builtin_cards();
+        registry
+            .load_str(include_str!("../../cards/vanilla.ron"))
+            .unwrap();
+
+        assert!(registry.name("Laboratory").is_ok());
+    }
+
+    #[test]
+    fn unknown_card_types_are_rejected() {
+        let mut registry = CardRegistry::new();
+        let result = registry.load_str(r#"[(name: "Thing", cost: 1, types: ["Gadget"])]"#);
+
+        assert!(matches!(
+            result,
+            Err(CardRegistryError::UnknownCardType { card_type, .. }) if card_type == "Gadget"
+        ));
+    }
+
+    #[test]
+    fn cards_cant_be_defined_twice() {
+        let mut registry = CardRegistry::with_builtin_cards();
+        let result = registry.load_str(r#"[(name: "Copper", cost: 0, types: ["Treasure"])]"#);
+
+        assert!(matches!(
+            result,
+            Err(CardRegistryError::DuplicateCard(CardNames::COPPER))
+        ));
+    }
+}
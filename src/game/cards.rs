@@ -1,10 +1,55 @@
-use super::effects::CardEffect;
+use std::{collections::HashSet, sync::Mutex};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::effects::{CardEffect, ImproveInstruction, Reaction, TrashInstruction};
 
 #[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
 pub struct CardName {
     name: &'static str,
 }
 
+/** Names of cards that were defined at runtime rather than in `CardNames` */
+static INTERNED_NAMES: Mutex<Option<HashSet<&'static str>>> = Mutex::new(None);
+
+impl CardName {
+    /** Card names are copied around everywhere, so names that come from card
+     * definition files get leaked to match the built-in ones (but only once each)
+     */
+    pub fn intern(name: &str) -> CardName {
+        let mut interned = INTERNED_NAMES
+            .lock()
+            .expect("BUG: card name interning panicked");
+        let interned = interned.get_or_insert_with(HashSet::new);
+        let name = match interned.get(name) {
+            Some(&name) => name,
+            None => {
+                let leaked: &'static str = Box::leak(name.to_owned().into_boxed_str());
+                interned.insert(leaked);
+                leaked
+            }
+        };
+        CardName { name }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl Serialize for CardName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name)
+    }
+}
+
+impl<'de> Deserialize<'de> for CardName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(CardName::intern(&name))
+    }
+}
+
 impl std::fmt::Debug for CardName {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!("[{}]", self.name))
@@ -21,17 +66,56 @@ impl CardTypes {
     pub const ACTION: CardType = CardType { name: "Action" };
     pub const TREASURE: CardType = CardType { name: "Treasure" };
     pub const VICTORY: CardType = CardType { name: "Victory" };
+    pub const CURSE: CardType = CardType { name: "Curse" };
+    pub const ATTACK: CardType = CardType { name: "Attack" };
+    pub const REACTION: CardType = CardType { name: "Reaction" };
+    pub const DURATION: CardType = CardType { name: "Duration" };
+
+    pub const ALL: [CardType; 7] = [
+        CardTypes::ACTION,
+        CardTypes::TREASURE,
+        CardTypes::VICTORY,
+        CardTypes::CURSE,
+        CardTypes::ATTACK,
+        CardTypes::REACTION,
+        CardTypes::DURATION,
+    ];
+
+    pub fn from_name(name: &str) -> Option<CardType> {
+        CardTypes::ALL.into_iter().find(|t| t.name == name)
+    }
 }
 
+#[derive(Clone)]
 pub struct Card {
     pub name: CardName,
     pub coins_cost: u8,
-    pub vp_value: u8,
+    pub vp_value: i8,
     pub effect: CardEffect,
+    /** What happens if this gets revealed in response to something */
+    pub reaction: Option<Reaction>,
     types: Vec<CardType>,
 }
 
 impl Card {
+    pub fn new(
+        name: CardName,
+        coins_cost: u8,
+        vp_value: i8,
+        types: Vec<CardType>,
+        effect: CardEffect,
+        reaction: Option<Reaction>,
+    ) -> Self {
+        Card {
+            name,
+            coins_cost,
+            vp_value,
+            effect,
+            reaction,
+            types,
+        }
+    }
+
     pub fn get_types(&self) -> impl Iterator<Item = CardType> + '_ {
         self.types.iter().cloned()
     }
@@ -55,8 +139,34 @@ impl CardNames {
     pub const PROVINCE: CardName = CardName { name: "Province" };
     pub const COLONY: CardName = CardName { name: "Colony" };
 
+    pub const CURSE: CardName = CardName { name: "Curse" };
+
     pub const SMITHY: CardName = CardName { name: "Smithy" };
     pub const VILLAGE: CardName = CardName { name: "Village" };
+    pub const CHAPEL: CardName = CardName { name: "Chapel" };
+    pub const MARKET: CardName = CardName { name: "Market" };
+    pub const FESTIVAL: CardName = CardName { name: "Festival" };
+    pub const WOODCUTTER: CardName = CardName { name: "Woodcutter" };
+    pub const MILITIA: CardName = CardName { name: "Militia" };
+    pub const WITCH: CardName = CardName { name: "Witch" };
+    pub const BANDIT: CardName = CardName { name: "Bandit" };
+    pub const MOAT: CardName = CardName { name: "Moat" };
+    pub const THRONE_ROOM: CardName = CardName {
+        name: "Throne Room",
+    };
+    pub const CELLAR: CardName = CardName { name: "Cellar" };
+    pub const WORKSHOP: CardName = CardName { name: "Workshop" };
+    pub const REMODEL: CardName = CardName { name: "Remodel" };
+    pub const MINE: CardName = CardName { name: "Mine" };
+    pub const MERCHANT: CardName = CardName { name: "Merchant" };
+    pub const WHARF: CardName = CardName { name: "Wharf" };
+    pub const CARAVAN: CardName = CardName { name: "Caravan" };
+    pub const MERCHANT_SHIP: CardName = CardName {
+        name: "Merchant Ship",
+    };
+    pub const FISHING_VILLAGE: CardName = CardName {
+        name: "Fishing Village",
+    };
 }
 
 pub struct Cards {}
@@ -67,16 +177,18 @@ impl Cards {
             coins_cost,
             vp_value: 0,
             types: vec![CardTypes::TREASURE],
+            reaction: None,
             effect: CardEffect::AddCoins(treasure_value),
         }
     }
 
-    fn basic_victory(coins_cost: u8, vp_value: u8, name: CardName) -> Card {
+    fn basic_victory(coins_cost: u8, vp_value: i8, name: CardName) -> Card {
         Card {
             name,
             coins_cost,
             vp_value,
             types: vec![CardTypes::VICTORY],
+            reaction: None,
             effect: CardEffect::None,
         }
     }
@@ -107,12 +219,24 @@ impl Cards {
         Self::basic_victory(11, 10, CardNames::COLONY)
     }
 
+    pub fn curse() -> Card {
+        Card {
+            name: CardNames::CURSE,
+            coins_cost: 0,
+            vp_value: -1,
+            types: vec![CardTypes::CURSE],
+            reaction: None,
+            effect: CardEffect::None,
+        }
+    }
+
     pub fn smithy() -> Card {
         Card {
             name: CardNames::SMITHY,
             coins_cost: 4,
             vp_value: 0,
             types: vec![CardTypes::ACTION],
+            reaction: None,
             effect: CardEffect::DrawCards(3),
         }
     }
@@ -122,10 +246,251 @@ impl Cards {
             coins_cost: 3,
             vp_value: 0,
             types: vec![CardTypes::ACTION],
+            reaction: None,
             effect: CardEffect::Sequence(Box::new([
                 CardEffect::DrawCards(1),
                 CardEffect::AddActions(2),
             ])),
         }
     }
+    pub fn chapel() -> Card {
+        Card {
+            name: CardNames::CHAPEL,
+            coins_cost: 2,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION],
+            reaction: None,
+            effect: CardEffect::TrashCardsFromHand(TrashInstruction {
+                min_cards_to_trash: 0,
+                max_cards_to_trash: 4,
+            }),
+        }
+    }
+    pub fn market() -> Card {
+        Card {
+            name: CardNames::MARKET,
+            coins_cost: 5,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION],
+            reaction: None,
+            effect: CardEffect::Sequence(Box::new([
+                CardEffect::DrawCards(1),
+                CardEffect::AddActions(1),
+                CardEffect::AddBuys(1),
+                CardEffect::AddCoins(1),
+            ])),
+        }
+    }
+    pub fn festival() -> Card {
+        Card {
+            name: CardNames::FESTIVAL,
+            coins_cost: 5,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION],
+            reaction: None,
+            effect: CardEffect::Sequence(Box::new([
+                CardEffect::AddActions(2),
+                CardEffect::AddBuys(1),
+                CardEffect::AddCoins(2),
+            ])),
+        }
+    }
+    pub fn woodcutter() -> Card {
+        Card {
+            name: CardNames::WOODCUTTER,
+            coins_cost: 3,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION],
+            reaction: None,
+            effect: CardEffect::Sequence(Box::new([
+                CardEffect::AddBuys(1),
+                CardEffect::AddCoins(2),
+            ])),
+        }
+    }
+    pub fn militia() -> Card {
+        Card {
+            name: CardNames::MILITIA,
+            coins_cost: 4,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION, CardTypes::ATTACK],
+            reaction: None,
+            effect: CardEffect::Sequence(Box::new([
+                CardEffect::AddCoins(2),
+                CardEffect::Attack(Box::new(CardEffect::DiscardDownTo(3))),
+            ])),
+        }
+    }
+    pub fn witch() -> Card {
+        Card {
+            name: CardNames::WITCH,
+            coins_cost: 5,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION, CardTypes::ATTACK],
+            reaction: None,
+            effect: CardEffect::Sequence(Box::new([
+                CardEffect::DrawCards(2),
+                CardEffect::Attack(Box::new(CardEffect::GainCard(CardNames::CURSE))),
+            ])),
+        }
+    }
+    pub fn bandit() -> Card {
+        Card {
+            name: CardNames::BANDIT,
+            coins_cost: 5,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION, CardTypes::ATTACK],
+            reaction: None,
+            effect: CardEffect::Sequence(Box::new([
+                CardEffect::GainCard(CardNames::GOLD),
+                CardEffect::Attack(Box::new(CardEffect::TrashRevealedTreasure(2))),
+            ])),
+        }
+    }
+    pub fn moat() -> Card {
+        Card {
+            name: CardNames::MOAT,
+            coins_cost: 2,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION, CardTypes::REACTION],
+            reaction: Some(Reaction::BlockAttack),
+            effect: CardEffect::DrawCards(2),
+        }
+    }
+    pub fn throne_room() -> Card {
+        Card {
+            name: CardNames::THRONE_ROOM,
+            coins_cost: 4,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION],
+            reaction: None,
+            effect: CardEffect::PlayActionMultipleTimes(2),
+        }
+    }
+    pub fn wharf() -> Card {
+        let now_and_next_turn =
+            CardEffect::Sequence(Box::new([CardEffect::DrawCards(2), CardEffect::AddBuys(1)]));
+        Card {
+            name: CardNames::WHARF,
+            coins_cost: 5,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION, CardTypes::DURATION],
+            reaction: None,
+            effect: CardEffect::Sequence(Box::new([
+                now_and_next_turn.clone(),
+                CardEffect::NextTurn(Box::new(now_and_next_turn)),
+            ])),
+        }
+    }
+    pub fn caravan() -> Card {
+        Card {
+            name: CardNames::CARAVAN,
+            coins_cost: 4,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION, CardTypes::DURATION],
+            reaction: None,
+            effect: CardEffect::Sequence(Box::new([
+                CardEffect::DrawCards(1),
+                CardEffect::AddActions(1),
+                CardEffect::NextTurn(Box::new(CardEffect::DrawCards(1))),
+            ])),
+        }
+    }
+    pub fn merchant_ship() -> Card {
+        Card {
+            name: CardNames::MERCHANT_SHIP,
+            coins_cost: 5,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION, CardTypes::DURATION],
+            reaction: None,
+            effect: CardEffect::Sequence(Box::new([
+                CardEffect::AddCoins(2),
+                CardEffect::NextTurn(Box::new(CardEffect::AddCoins(2))),
+            ])),
+        }
+    }
+    pub fn fishing_village() -> Card {
+        Card {
+            name: CardNames::FISHING_VILLAGE,
+            coins_cost: 3,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION, CardTypes::DURATION],
+            reaction: None,
+            effect: CardEffect::Sequence(Box::new([
+                CardEffect::AddActions(2),
+                CardEffect::AddCoins(1),
+                CardEffect::NextTurn(Box::new(CardEffect::Sequence(Box::new([
+                    CardEffect::AddActions(1),
+                    CardEffect::AddCoins(1),
+                ])))),
+            ])),
+        }
+    }
+    pub fn cellar() -> Card {
+        Card {
+            name: CardNames::CELLAR,
+            coins_cost: 2,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION],
+            reaction: None,
+            effect: CardEffect::Sequence(Box::new([
+                CardEffect::AddActions(1),
+                CardEffect::DiscardAndDraw,
+            ])),
+        }
+    }
+    pub fn workshop() -> Card {
+        Card {
+            name: CardNames::WORKSHOP,
+            coins_cost: 3,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION],
+            reaction: None,
+            effect: CardEffect::GainCardCostingUpTo(4),
+        }
+    }
+    pub fn remodel() -> Card {
+        Card {
+            name: CardNames::REMODEL,
+            coins_cost: 4,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION],
+            reaction: None,
+            effect: CardEffect::Improve(ImproveInstruction {
+                max_extra_cost: 2,
+                optional: false,
+                treasures_only: false,
+                gain_to_hand: false,
+            }),
+        }
+    }
+    pub fn mine() -> Card {
+        Card {
+            name: CardNames::MINE,
+            coins_cost: 5,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION],
+            reaction: None,
+            effect: CardEffect::Improve(ImproveInstruction {
+                max_extra_cost: 3,
+                optional: true,
+                treasures_only: true,
+                gain_to_hand: true,
+            }),
+        }
+    }
+    pub fn merchant() -> Card {
+        Card {
+            name: CardNames::MERCHANT,
+            coins_cost: 3,
+            vp_value: 0,
+            types: vec![CardTypes::ACTION],
+            reaction: None,
+            effect: CardEffect::Sequence(Box::new([
+                CardEffect::DrawCards(1),
+                CardEffect::AddActions(1),
+                CardEffect::BonusOnFirstPlay(CardNames::SILVER, Box::new(CardEffect::AddCoins(1))),
+            ])),
+        }
+    }
 }
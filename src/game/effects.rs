@@ -1,16 +1,78 @@
-#[derive(Debug, Clone, PartialEq, Eq)]
+use serde::{Deserialize, Serialize};
+
+use super::cards::CardName;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CardEffect {
+    #[default]
     None,
     Sequence(Box<[CardEffect]>),
     AddActions(u8),
-    // AddBuys(u8),
+    AddBuys(u8),
     AddCoins(u8),
     DrawCards(u8),
-    // TrashCardsFromHand(TrashInstruction),
+    TrashCardsFromHand(TrashInstruction),
+    /** Gain a copy of a card from the supply (if there are any left) */
+    GainCard(CardName),
+    /** The player picks cards to discard until they have this many left */
+    DiscardDownTo(u8),
+    /** Reveal this many cards from the top of the deck, trash a revealed
+     * treasure other than Copper, and discard the rest */
+    TrashRevealedTreasure(u8),
+    /** Resolve the inner effect against each other player in turn order */
+    Attack(Box<CardEffect>),
+    /** The player may pick an action card from their hand to play this many times */
+    PlayActionMultipleTimes(u8),
+    /** Discard any number of cards from hand, then draw that many */
+    DiscardAndDraw,
+    /** Gain a card from the supply costing up to this much */
+    GainCardCostingUpTo(u8),
+    /** Trash a card from hand, and gain a better one */
+    Improve(ImproveInstruction),
+    /** Resolve the effect the first time this turn that the named card gets
+     * played (unless it's already been played) */
+    BonusOnFirstPlay(CardName, Box<CardEffect>),
+    /** Resolve the inner effect at the start of the player's next turn.
+     * The card (and anything that played it) stays in play until then */
+    NextTurn(Box<CardEffect>),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TrashInstruction {
     pub min_cards_to_trash: u8,
     pub max_cards_to_trash: u8,
 }
+
+/** Remodel and Mine both swap a card from hand for one that costs a bit more */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImproveInstruction {
+    /** How much more the gained card can cost than the trashed one */
+    pub max_extra_cost: u8,
+    /** Whether the player can choose not to trash anything */
+    pub optional: bool,
+    /** Whether both cards have to be treasures */
+    pub treasures_only: bool,
+    /** Whether the gained card goes into hand rather than the discard pile */
+    pub gain_to_hand: bool,
+}
+
+/** What a reaction card does when it gets revealed */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Reaction {
+    /** Whoever revealed it is unaffected by the attack */
+    BlockAttack,
+}
+
+impl CardEffect {
+    /** Whether this effect, or anything it's made up of, matches `predicate` */
+    pub fn any(&self, predicate: &impl Fn(&CardEffect) -> bool) -> bool {
+        predicate(self)
+            || match self {
+                CardEffect::Sequence(effects) => effects.iter().any(|e| e.any(predicate)),
+                CardEffect::Attack(effect)
+                | CardEffect::NextTurn(effect)
+                | CardEffect::BonusOnFirstPlay(_, effect) => effect.any(predicate),
+                _ => false,
+            }
+    }
+}
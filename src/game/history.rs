@@ -0,0 +1,516 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    cards::CardName,
+    effects::CardEffect,
+    shuffler::ShufflerState,
+    snapshot::{GameSnapshot, ZonesSnapshot},
+};
+
+/** One of the places a player keeps their own cards */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Zone {
+    Deck,
+    Hand,
+    InPlay,
+    SetAside,
+    Discard,
+    /** Cards taken off the deck to look at (eg by Bandit) before they go somewhere else */
+    Revealed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Place {
+    /** A zone belonging to the player in this seat */
+    Player(usize, Zone),
+    /** The pile that started with this card on top */
+    Supply(CardName),
+    Trash,
+}
+
+/** Exactly where a card is, counting from the bottom of a pile or the start of a hand */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub place: Place,
+    pub index: usize,
+}
+
+impl Location {
+    pub fn new(place: Place, index: usize) -> Self {
+        Location { place, index }
+    }
+}
+
+/** Which rule moved a card, so a history can be read as well as replayed */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MoveReason {
+    Draw,
+    Reveal,
+    Play,
+    SetAside,
+    StartOfTurn,
+    Gain,
+    Buy,
+    Discard,
+    Trash,
+    Cleanup,
+}
+
+/** Everything that can happen to the state of a game, described in enough
+ * detail that it can be undone again (see `inverse`).
+ *
+ * Changes are applied to a `GameSnapshot` rather than a running game, so that
+ * the cards themselves don't need to be rebuilt for every step.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StateChange {
+    Move {
+        reason: MoveReason,
+        card: CardName,
+        from: Location,
+        to: Location,
+    },
+    /** A player's discard pile becoming their (previously empty) deck. `shuffler`
+     * is the shuffler's state before and after, if it has one.
+     */
+    Shuffle {
+        player: usize,
+        discard: Vec<CardName>,
+        deck: Vec<CardName>,
+        shuffler: Option<(ShufflerState, ShufflerState)>,
+    },
+    Unshuffle {
+        player: usize,
+        discard: Vec<CardName>,
+        deck: Vec<CardName>,
+        shuffler: Option<(ShufflerState, ShufflerState)>,
+    },
+    /** Player 0 starting a turn also starts a new round, so it moves the turn counter on */
+    TurnStarted {
+        player: usize,
+        turn: u8,
+    },
+    TurnUnstarted {
+        player: usize,
+        turn: u8,
+    },
+    /** Duration cards leaving something to do next turn, or it getting done */
+    NextTurnEffects {
+        player: usize,
+        before: Vec<(CardName, CardEffect)>,
+        after: Vec<(CardName, CardEffect)>,
+    },
+}
+
+impl StateChange {
+    /** The change that puts things back how they were before this one */
+    pub fn inverse(&self) -> StateChange {
+        match self.clone() {
+            StateChange::Move {
+                reason,
+                card,
+                from,
+                to,
+            } => StateChange::Move {
+                reason,
+                card,
+                from: to,
+                to: from,
+            },
+            StateChange::Shuffle {
+                player,
+                discard,
+                deck,
+                shuffler,
+            } => StateChange::Unshuffle {
+                player,
+                discard,
+                deck,
+                shuffler,
+            },
+            StateChange::Unshuffle {
+                player,
+                discard,
+                deck,
+                shuffler,
+            } => StateChange::Shuffle {
+                player,
+                discard,
+                deck,
+                shuffler,
+            },
+            StateChange::TurnStarted { player, turn } => {
+                StateChange::TurnUnstarted { player, turn }
+            }
+            StateChange::TurnUnstarted { player, turn } => {
+                StateChange::TurnStarted { player, turn }
+            }
+            StateChange::NextTurnEffects {
+                player,
+                before,
+                after,
+            } => StateChange::NextTurnEffects {
+                player,
+                before: after,
+                after: before,
+            },
+        }
+    }
+
+    /** Panics if the board isn't in the state the change expects, since that means
+     * either the change was recorded wrongly or it's being applied out of order
+     */
+    pub fn apply(&self, board: &mut GameSnapshot) {
+        match self {
+            StateChange::Move { card, from, to, .. } => {
+                let removed = cards_at(board, from.place).remove(from.index);
+                assert!(
+                    removed == *card,
+                    "BUG: expected {:?} at {:?} but found {:?}",
+                    card,
+                    from,
+                    removed
+                );
+                cards_at(board, to.place).insert(to.index, removed);
+            }
+            StateChange::Shuffle {
+                player,
+                discard,
+                deck,
+                shuffler,
+            } => {
+                let zones = &mut board.players[*player].zones;
+                assert!(
+                    zones.deck.is_empty(),
+                    "BUG: shuffled with cards still in the deck"
+                );
+                assert!(
+                    zones.discard == *discard,
+                    "BUG: shuffled a different discard pile"
+                );
+                zones.discard.clear();
+                zones.deck = deck.clone();
+                if let Some((before, after)) = shuffler {
+                    update_shuffler(board, before, after);
+                }
+            }
+            StateChange::Unshuffle {
+                player,
+                discard,
+                deck,
+                shuffler,
+            } => {
+                let zones = &mut board.players[*player].zones;
+                assert!(
+                    zones.discard.is_empty(),
+                    "BUG: unshuffled onto a discard pile"
+                );
+                assert!(zones.deck == *deck, "BUG: unshuffled a different deck");
+                zones.deck.clear();
+                zones.discard = discard.clone();
+                if let Some((before, after)) = shuffler {
+                    update_shuffler(board, after, before);
+                }
+            }
+            StateChange::TurnStarted { player, turn } => {
+                assert!(board.next_seat == *player, "BUG: turn taken out of order");
+                if *player == 0 {
+                    board.turn_counter += 1;
+                }
+                assert!(board.turn_counter == *turn, "BUG: turn counter out of step");
+                board.players[*player].turns_taken += 1;
+                board.next_seat = (player + 1) % board.players.len();
+            }
+            StateChange::TurnUnstarted { player, turn } => {
+                assert!(
+                    board.next_seat == (player + 1) % board.players.len(),
+                    "BUG: turn undone out of order"
+                );
+                assert!(board.turn_counter == *turn, "BUG: turn counter out of step");
+                board.players[*player].turns_taken -= 1;
+                board.next_seat = *player;
+                if *player == 0 {
+                    board.turn_counter -= 1;
+                }
+            }
+            StateChange::NextTurnEffects {
+                player,
+                before,
+                after,
+            } => {
+                let effects = &mut board.players[*player].zones.next_turn_effects;
+                assert!(effects == before, "BUG: next turn effects out of step");
+                *effects = after.clone();
+            }
+        }
+    }
+}
+
+fn cards_at(board: &mut GameSnapshot, place: Place) -> &mut Vec<CardName> {
+    match place {
+        Place::Player(seat, zone) => {
+            let zones: &mut ZonesSnapshot = &mut board.players[seat].zones;
+            match zone {
+                Zone::Deck => &mut zones.deck,
+                Zone::Hand => &mut zones.hand,
+                Zone::InPlay => &mut zones.in_play,
+                Zone::SetAside => &mut zones.set_aside,
+                Zone::Discard => &mut zones.discard,
+                Zone::Revealed => &mut zones.revealed,
+            }
+        }
+        Place::Supply(name) => board
+            .supply
+            .iter_mut()
+            .find(|(pile, _)| *pile == name)
+            .map(|(_, cards)| cards)
+            .expect("BUG: moved a card to or from a pile that isn't in the supply"),
+        Place::Trash => &mut board.trash,
+    }
+}
+
+/** Players who share a shuffler all have the same state, so they all need updating */
+fn update_shuffler(board: &mut GameSnapshot, from: &ShufflerState, to: &ShufflerState) {
+    for player in &mut board.players {
+        if player.shuffler.as_ref() == Some(from) {
+            player.shuffler = Some(to.clone());
+        }
+    }
+}
+
+/** A game as a starting position plus everything that happened after it, which
+ * can be stepped through in either direction. The board at any point is a
+ * `GameSnapshot`, so a game can be restored from there (eg to see what would
+ * have happened if someone had bought something else).
+ */
+#[derive(Debug, Clone)]
+pub struct GameHistory {
+    changes: Vec<StateChange>,
+    /** How many of the changes have been applied to the board */
+    position: usize,
+    board: GameSnapshot,
+}
+
+impl GameHistory {
+    /** Starts at `start`, before any of the changes */
+    pub fn new(start: GameSnapshot, changes: Vec<StateChange>) -> Self {
+        GameHistory {
+            changes,
+            position: 0,
+            board: start,
+        }
+    }
+
+    pub fn board(&self) -> &GameSnapshot {
+        &self.board
+    }
+
+    pub fn changes(&self) -> &[StateChange] {
+        &self.changes
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /** Applies the next change, if there is one */
+    pub fn step_forward(&mut self) -> Option<&StateChange> {
+        let change = self.changes.get(self.position)?;
+        change.apply(&mut self.board);
+        self.position += 1;
+        Some(change)
+    }
+
+    /** Undoes the last change, if there is one */
+    pub fn step_back(&mut self) -> Option<&StateChange> {
+        self.position = self.position.checked_sub(1)?;
+        let change = &self.changes[self.position];
+        change.inverse().apply(&mut self.board);
+        Some(change)
+    }
+
+    /** Steps backwards or forwards until `position` changes have been applied */
+    pub fn go_to(&mut self, position: usize) {
+        assert!(
+            position <= self.changes.len(),
+            "there are only {} changes",
+            self.changes.len()
+        );
+        while self.position < position {
+            self.step_forward();
+        }
+        while self.position > position {
+            self.step_back();
+        }
+    }
+
+    pub fn go_to_end(&mut self) {
+        self.go_to(self.changes.len())
+    }
+
+    /** Every turn in the history, as (turn counter, seat) */
+    pub fn turns(&self) -> impl Iterator<Item = (u8, usize)> + '_ {
+        self.changes.iter().filter_map(|c| match c {
+            StateChange::TurnStarted { player, turn } => Some((*turn, *player)),
+            _ => None,
+        })
+    }
+
+    /** Goes back (or forward) to just before `player` started their turn
+     * on round `turn`, or returns None if that turn never happened
+     */
+    pub fn rewind_to_turn(&mut self, turn: u8, player: usize) -> Option<&GameSnapshot> {
+        let started = StateChange::TurnStarted { player, turn };
+        let position = self.changes.iter().position(|c| *c == started)?;
+        self.go_to(position);
+        Some(&self.board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::game::{
+        card_registry::CardRegistry,
+        cards::{Card, CardNames},
+        kingdom::Kingdom,
+        logs::tests::null_log,
+        players::{tests::busy_agent, Agent2, Agents},
+        shuffler::{RandomShuffler, Shuffler},
+        Game,
+    };
+
+    #[test]
+    fn changes_can_be_undone() {
+        let change = StateChange::Move {
+            reason: MoveReason::Buy,
+            card: CardNames::SILVER,
+            from: Location::new(Place::Supply(CardNames::SILVER), 39),
+            to: Location::new(Place::Player(1, Zone::Discard), 3),
+        };
+
+        let inverse = change.inverse();
+
+        assert_eq!(
+            Place::Supply(CardNames::SILVER),
+            match &inverse {
+                StateChange::Move { to, .. } => to.place,
+                _ => panic!("expected a move"),
+            }
+        );
+        assert_eq!(change, inverse.inverse());
+    }
+
+    #[test]
+    fn a_history_ends_where_the_game_is_and_starts_where_it_started() {
+        let registry = CardRegistry::with_builtin_cards();
+        for preset in ["Setting Sail", "Cursed"] {
+            let kingdom = Kingdom::preset(&registry, preset).unwrap();
+            let (mut p1, mut p2) = (busy_agent(&kingdom), busy_agent(&kingdom));
+            let shuffler = RandomShuffler::new(1234);
+            let mut game = Game::new(null_log());
+            game.add_player("P1", &mut p1, &shuffler);
+            game.add_player("P2", &mut p2, &shuffler);
+            game.populate_basic_kingdom();
+            game.populate_kingdom(&registry, &kingdom).unwrap();
+            game.deal_starting_hands();
+            let start = game.snapshot();
+
+            game.start_history();
+            game.play_rounds(15);
+            let mut history = game.history().unwrap();
+
+            assert_eq!(&game.snapshot(), history.board());
+            history.go_to(0);
+            assert_eq!(&start, history.board());
+        }
+    }
+
+    #[test]
+    fn stepping_back_and_forward_again_ends_up_in_the_same_place() {
+        let registry = CardRegistry::with_builtin_cards();
+        let shuffler = RandomShuffler::new(99);
+        let (mut p1, mut p2) = (Agents::big_money_witch(), Agents::chapel_big_money());
+        let mut game = Game::new(null_log());
+        game.add_player("P1", &mut p1, &shuffler);
+        game.add_player("P2", &mut p2, &shuffler);
+        game.populate_basic_kingdom();
+        game.populate_supply_from(&registry, "Witch").unwrap();
+        game.populate_supply_from(&registry, "Chapel").unwrap();
+        game.deal_starting_hands();
+        game.start_history();
+        game.play_rounds(10);
+        let mut history = game.history().unwrap();
+        let end = history.board().clone();
+
+        let last = history.step_back().cloned();
+        let second_last = history.step_back().cloned();
+
+        assert_eq!(second_last, history.step_forward().cloned());
+        assert_eq!(last, history.step_forward().cloned());
+        assert_eq!(&end, history.board());
+        assert!(history.step_forward().is_none());
+    }
+
+    fn simple_game<'a>(
+        p1: &'a mut dyn Agent2,
+        p2: &'a mut dyn Agent2,
+        shuffler: &'a dyn Shuffler<Card>,
+    ) -> Game<'a> {
+        let mut game = Game::new(null_log());
+        game.add_player("P1", p1, shuffler);
+        game.add_player("P2", p2, shuffler);
+        game.populate_basic_kingdom();
+        game
+    }
+
+    #[test]
+    fn a_game_rewound_to_the_start_of_a_turn_can_be_played_again_from_there() {
+        let registry = CardRegistry::with_builtin_cards();
+        let shuffler = RandomShuffler::new(1234);
+        let (mut p1, mut p2) = (Agents::simple_big_money(), Agents::greedy_for_duchies());
+        let mut original = simple_game(&mut p1, &mut p2, &shuffler);
+        original.deal_starting_hands();
+        original.start_history();
+        original.play_rounds(8);
+        let mut history = original.history().unwrap();
+
+        // partway through a round, so the first player doesn't go next
+        let rewound = history.rewind_to_turn(5, 1).unwrap().clone();
+        assert_eq!(1, rewound.next_seat);
+        assert_eq!(4, rewound.players[1].turns_taken);
+
+        let shuffler = RandomShuffler::from_state(rewound.players[0].shuffler.clone().unwrap());
+        let (mut p1, mut p2) = (Agents::simple_big_money(), Agents::greedy_for_duchies());
+        let mut replayed = Game::restore(
+            &rewound,
+            &registry,
+            null_log(),
+            vec![(&mut p1, &shuffler), (&mut p2, &shuffler)],
+        )
+        .unwrap();
+        replayed.play_rounds(4);
+
+        assert_eq!(original.snapshot(), replayed.snapshot());
+    }
+
+    #[test]
+    fn turns_that_never_happened_cant_be_rewound_to() {
+        let shuffler = RandomShuffler::new(1234);
+        let (mut p1, mut p2) = (Agents::simple_big_money(), Agents::greedy_for_duchies());
+        let mut game = simple_game(&mut p1, &mut p2, &shuffler);
+        game.deal_starting_hands();
+        game.start_history();
+        game.play_rounds(3);
+        let mut history = game.history().unwrap();
+
+        assert_eq!(
+            vec![(1, 0), (1, 1), (2, 0), (2, 1), (3, 0), (3, 1)],
+            history.turns().collect_vec()
+        );
+        assert!(history.rewind_to_turn(4, 0).is_none());
+        assert!(history.rewind_to_turn(1, 2).is_none());
+    }
+}
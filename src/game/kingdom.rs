@@ -0,0 +1,296 @@
+use std::fmt::{Display, Formatter};
+
+use itertools::Itertools;
+
+use super::{
+    card_registry::{CardRegistry, CardRegistryError},
+    cards::{Card, CardName, CardTypes},
+    effects::CardEffect,
+    shuffler::{RandomShuffler, Shuffler},
+};
+
+/** How many kingdom piles a game has, on top of the basic treasures and victory cards */
+pub const KINGDOM_SIZE: usize = 10;
+
+/** How many random kingdoms to try before deciding the constraints can't be met */
+const MAX_ATTEMPTS: usize = 10_000;
+
+/** Sets of kingdom cards that play well together, by name.
+ * "First Game" is the set the base game recommends, the others only use cards we've implemented.
+ */
+pub const PRESETS: &[(&str, [&str; KINGDOM_SIZE])] = &[
+    (
+        "First Game",
+        [
+            "Cellar", "Market", "Merchant", "Militia", "Mine", "Moat", "Remodel", "Smithy",
+            "Village", "Workshop",
+        ],
+    ),
+    (
+        "Setting Sail",
+        [
+            "Caravan",
+            "Cellar",
+            "Fishing Village",
+            "Market",
+            "Merchant Ship",
+            "Militia",
+            "Moat",
+            "Smithy",
+            "Throne Room",
+            "Wharf",
+        ],
+    ),
+    (
+        "Cursed",
+        [
+            "Bandit",
+            "Chapel",
+            "Festival",
+            "Militia",
+            "Moat",
+            "Remodel",
+            "Throne Room",
+            "Village",
+            "Witch",
+            "Woodcutter",
+        ],
+    ),
+];
+
+#[derive(Debug)]
+pub enum KingdomError {
+    UnknownPreset(String),
+    Registry(CardRegistryError),
+    /** There aren't enough cards to choose from once the constraints are applied */
+    NotEnoughCards {
+        available: usize,
+    },
+    Unsatisfiable(KingdomConstraints),
+}
+
+impl Display for KingdomError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KingdomError::UnknownPreset(name) => write!(
+                f,
+                "there's no preset called {:?} (try one of {})",
+                name,
+                PRESETS.iter().map(|(n, _)| n).join(", ")
+            ),
+            KingdomError::Registry(e) => e.fmt(f),
+            KingdomError::NotEnoughCards { available } => write!(
+                f,
+                "a kingdom needs {} action cards, but only {} are available",
+                KINGDOM_SIZE, available
+            ),
+            KingdomError::Unsatisfiable(constraints) => {
+                write!(f, "couldn't find a kingdom meeting {:?}", constraints)
+            }
+        }
+    }
+}
+
+impl std::error::Error for KingdomError {}
+
+/** Things a randomly-picked kingdom has to have (or not have) */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KingdomConstraints {
+    /** At least one card gives +Buy */
+    pub require_plus_buy: bool,
+    pub no_attacks: bool,
+    /** At least one card costing 3 or less, one costing 4, and one costing 5 or more */
+    pub cost_spread: bool,
+}
+
+impl KingdomConstraints {
+    fn allows(&self, card: &Card) -> bool {
+        !(self.no_attacks && is_attack(card))
+    }
+
+    fn satisfied_by(&self, cards: &[&Card]) -> bool {
+        let plus_buy = cards
+            .iter()
+            .any(|c| c.effect.any(&|e| matches!(e, CardEffect::AddBuys(_))));
+        let costs = |range: std::ops::RangeInclusive<u8>| {
+            cards.iter().any(|c| range.contains(&c.coins_cost))
+        };
+        (!self.require_plus_buy || plus_buy)
+            && (!self.cost_spread || (costs(0..=3) && costs(4..=4) && costs(5..=u8::MAX)))
+    }
+}
+
+fn is_attack(card: &Card) -> bool {
+    card.get_types().any(|t| t == CardTypes::ATTACK)
+}
+
+fn is_action(card: &Card) -> bool {
+    card.get_types().any(|t| t == CardTypes::ACTION)
+}
+
+/** The kingdom cards available in a game, cheapest first */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Kingdom {
+    pub cards: Vec<CardName>,
+}
+
+impl Kingdom {
+    fn new(mut cards: Vec<&Card>) -> Self {
+        cards.sort_by_key(|c| (c.coins_cost, c.name.as_str()));
+        Kingdom {
+            cards: cards.iter().map(|c| c.name).collect(),
+        }
+    }
+
+    pub fn preset(registry: &CardRegistry, name: &str) -> Result<Self, KingdomError> {
+        let (_, cards) = PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .ok_or_else(|| KingdomError::UnknownPreset(name.to_owned()))?;
+        let cards = cards
+            .iter()
+            .map(|&card| {
+                let name = registry.name(card).map_err(KingdomError::Registry)?;
+                Ok(registry
+                    .get(name)
+                    .expect("BUG: registry should have a card it just named"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Kingdom::new(cards))
+    }
+
+    /** Picks action cards from the registry at random, trying again until
+     * the constraints are met
+     */
+    pub fn random(
+        registry: &CardRegistry,
+        seed: u64,
+        constraints: KingdomConstraints,
+    ) -> Result<Self, KingdomError> {
+        let candidates = registry
+            .names()
+            .filter_map(|name| registry.get(name))
+            .filter(|c| is_action(c) && constraints.allows(c))
+            .collect_vec();
+        if candidates.len() < KINGDOM_SIZE {
+            return Err(KingdomError::NotEnoughCards {
+                available: candidates.len(),
+            });
+        }
+
+        let shuffler = RandomShuffler::new(seed);
+        for _ in 0..MAX_ATTEMPTS {
+            let mut pool = candidates.clone();
+            let picked = shuffler
+                .shuffle(&mut pool)
+                .into_iter()
+                .take(KINGDOM_SIZE)
+                .collect_vec();
+            if constraints.satisfied_by(&picked) {
+                return Ok(Kingdom::new(picked));
+            }
+        }
+        Err(KingdomError::Unsatisfiable(constraints))
+    }
+}
+
+impl Display for Kingdom {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.cards.iter().map(|c| c.as_str()).join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::cards::CardNames;
+
+    fn cards<'r>(registry: &'r CardRegistry, kingdom: &Kingdom) -> Vec<&'r Card> {
+        kingdom
+            .cards
+            .iter()
+            .map(|&name| registry.get(name).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn every_preset_only_uses_builtin_cards() {
+        let registry = CardRegistry::with_builtin_cards();
+
+        for (name, _) in PRESETS {
+            let kingdom = Kingdom::preset(&registry, name).unwrap();
+            assert_eq!(KINGDOM_SIZE, kingdom.cards.iter().unique().count());
+        }
+    }
+
+    #[test]
+    fn presets_are_sorted_by_cost() {
+        let registry = CardRegistry::with_builtin_cards();
+
+        let kingdom = Kingdom::preset(&registry, "first game").unwrap();
+
+        assert_eq!(CardNames::CELLAR, kingdom.cards[0]);
+        assert_eq!(CardNames::MINE, kingdom.cards[9]);
+    }
+
+    #[test]
+    fn unknown_presets_are_rejected() {
+        let registry = CardRegistry::with_builtin_cards();
+
+        assert!(matches!(
+            Kingdom::preset(&registry, "Second Game"),
+            Err(KingdomError::UnknownPreset(_))
+        ));
+    }
+
+    #[test]
+    fn random_kingdoms_depend_only_on_the_seed() {
+        let registry = CardRegistry::with_builtin_cards();
+        let random = |seed| Kingdom::random(&registry, seed, Default::default()).unwrap();
+
+        assert_eq!(random(1), random(1));
+        assert_ne!(random(1), random(2));
+    }
+
+    #[test]
+    fn random_kingdoms_only_use_action_cards() {
+        let registry = CardRegistry::with_builtin_cards();
+
+        for seed in 0..20 {
+            let kingdom = Kingdom::random(&registry, seed, Default::default()).unwrap();
+            assert_eq!(KINGDOM_SIZE, kingdom.cards.iter().unique().count());
+            assert!(cards(&registry, &kingdom).iter().all(|c| is_action(c)));
+        }
+    }
+
+    #[test]
+    fn random_kingdoms_respect_constraints() {
+        let registry = CardRegistry::with_builtin_cards();
+        let constraints = KingdomConstraints {
+            require_plus_buy: true,
+            no_attacks: true,
+            cost_spread: true,
+        };
+
+        for seed in 0..20 {
+            let kingdom = Kingdom::random(&registry, seed, constraints).unwrap();
+            let cards = cards(&registry, &kingdom);
+            assert!(constraints.satisfied_by(&cards));
+            assert!(!cards.iter().any(|c| is_attack(c)));
+        }
+    }
+
+    #[test]
+    fn small_registries_cant_make_a_kingdom() {
+        let registry = CardRegistry::with_builtin_cards();
+        let mut small = CardRegistry::new();
+        for name in [CardNames::VILLAGE, CardNames::SMITHY] {
+            small.add(registry.make(name).unwrap()).unwrap();
+        }
+
+        assert!(matches!(
+            Kingdom::random(&small, 1, Default::default()),
+            Err(KingdomError::NotEnoughCards { available: 2 })
+        ));
+    }
+}
@@ -1,19 +1,58 @@
 use derive_more::Constructor;
 use itertools::Itertools;
-use std::{cell::RefCell, ops::Deref, rc::Rc};
+use serde::{ser::SerializeMap, Serialize};
+use std::{
+    cell::{Cell, RefCell},
+    io::Write,
+    ops::Deref,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
 
 use self::span_details_are_private::*;
-use super::{cards::CardName, player_counters::PlayerCounters};
+use super::{
+    cards::CardName, history::StateChange, player_counters::PlayerCounters, players::InvalidChoice,
+    results::GameEndReason,
+};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", content = "data")]
 pub enum GameEvent {
     CardPlayed(CardName, PlayerCounters),
     CardBoughtGained(CardName),
+    CardGained(CardName),
     DrawCards(usize),
+    RevealCards(usize),
     Shuffle(),
+    Trashed(CardName),
+    Discarded(CardName),
+    ReactionRevealed(CardName),
+    UnaffectedByAttack,
+    GameEnded(GameEndReason),
+    InvalidChoice(InvalidChoice),
 }
 
-pub struct SpanData<'a>(&'a [(&'static str, &'a dyn std::fmt::Debug)]);
+/** Something a span can be tagged with. These are kept to a few simple types
+ * so that logs can write them out as something other than Debug text.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum SpanValue<'a> {
+    Text(&'a str),
+    Number(u64),
+    Card(CardName),
+}
+impl std::fmt::Debug for SpanValue<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpanValue::Text(text) => text.fmt(f),
+            SpanValue::Number(number) => number.fmt(f),
+            SpanValue::Card(card) => card.fmt(f),
+        }
+    }
+}
+
+pub struct SpanData<'a>(&'a [(&'static str, SpanValue<'a>)]);
 impl SpanData<'_> {
     fn empty() -> Self {
         SpanData(&[])
@@ -29,6 +68,15 @@ impl std::fmt::Debug for SpanData<'_> {
         write!(f, "{}", str)
     }
 }
+impl Serialize for SpanData<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
 
 pub trait GameLogInner {
     fn record(&self, event: GameEvent);
@@ -38,22 +86,51 @@ pub trait GameLogInner {
 
 pub struct GameLog {
     inner: Rc<dyn GameLogInner>,
+    /** Only kept once someone asks for a `GameHistory`, since most games don't need one */
+    changes: RefCell<Option<Vec<StateChange>>>,
 }
 impl GameLog {
     pub fn new(inner: Rc<dyn GameLogInner>) -> Self {
-        GameLog { inner }
+        GameLog {
+            inner,
+            changes: None.into(),
+        }
     }
     pub fn record(&self, event: GameEvent) {
         self.inner.record(event)
     }
+    /** Starts keeping track of changes to the game state, forgetting any from before */
+    pub fn start_recording_changes(&self) {
+        *self.changes.borrow_mut() = Some(vec![]);
+    }
+    /** Changes are only worth describing (which can mean copying whole piles) if
+     * they're being kept
+     */
+    pub fn is_recording_changes(&self) -> bool {
+        self.changes.borrow().is_some()
+    }
+    pub fn change(&self, change: StateChange) {
+        if let Some(changes) = self.changes.borrow_mut().as_mut() {
+            changes.push(change);
+        }
+    }
+    pub fn recorded_changes(&self) -> Option<Vec<StateChange>> {
+        self.changes.borrow().clone()
+    }
     pub fn enter_turn(&self, player_name: &str, turn_counter: u8) -> GameLogSpan {
-        let data: &[(&'static str, &dyn std::fmt::Debug)] = &[
-            ("player_name", &player_name),
-            ("turn_counter", &turn_counter),
+        let data = [
+            ("player_name", SpanValue::Text(player_name)),
+            ("turn_counter", SpanValue::Number(turn_counter.into())),
         ];
-        let data = SpanData(data);
+        let data = SpanData(&data);
         GameLogSpan::new(self.inner.enter_span("turn", data), self.inner.clone())
     }
+    pub fn enter_start_of_turn(&self) -> GameLogSpan {
+        GameLogSpan::new(
+            self.inner.enter_span("start of turn", SpanData::empty()),
+            self.inner.clone(),
+        )
+    }
     pub fn enter_action_phase(&self) -> GameLogSpan {
         GameLogSpan::new(
             self.inner.enter_span("action phase", SpanData::empty()),
@@ -66,6 +143,23 @@ impl GameLog {
             self.inner.clone(),
         )
     }
+    pub fn enter_attacked(&self, player_name: &str) -> GameLogSpan {
+        let data = [("player_name", SpanValue::Text(player_name))];
+        GameLogSpan::new(
+            self.inner.enter_span("attacked", SpanData(&data)),
+            self.inner.clone(),
+        )
+    }
+    pub fn enter_repeat(&self, card: CardName, times: u8) -> GameLogSpan {
+        let data = [
+            ("card", SpanValue::Card(card)),
+            ("times", SpanValue::Number(times.into())),
+        ];
+        GameLogSpan::new(
+            self.inner.enter_span("repeat", SpanData(&data)),
+            self.inner.clone(),
+        )
+    }
     pub fn enter_cleanup(&self) -> GameLogSpan {
         GameLogSpan::new(
             self.inner.enter_span("cleanup", SpanData::empty()),
@@ -89,6 +183,11 @@ mod span_details_are_private {
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Constructor)]
     pub struct SpanId(u64);
+    impl SpanId {
+        pub(in crate::game::logs) fn value(self) -> u64 {
+            self.0
+        }
+    }
 
     #[derive(Constructor)]
     pub struct GameLogSpan {
@@ -109,12 +208,11 @@ mod span_details_are_private {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct ConsoleLog {
     indent: RefCell<u64>,
 }
 impl ConsoleLog {
-    #[allow(dead_code)]
     pub fn new() -> Self {
         ConsoleLog { indent: 0.into() }
     }
@@ -154,12 +252,242 @@ impl GameLogInner for NullLog {
     fn exit_span(&self, _id: SpanId) {}
 }
 
+/** For a game that gets played from the start over and over again, getting a
+ * bit further each time (like a `SteppedGame`). Each call only gets passed on
+ * the first time it happens, so the inner log sees the game once, as it goes.
+ */
+pub struct ReplayLog {
+    inner: Rc<dyn GameLogInner>,
+    /** How many calls the inner log has seen */
+    passed_on: Cell<usize>,
+    /** How many calls there have been since the last rewind */
+    calls: Cell<usize>,
+    /** The inner log's id for each span, in the order they were entered */
+    span_ids: RefCell<Vec<SpanId>>,
+    spans_entered: Cell<usize>,
+    /** Where a rewind goes back to, as (calls, spans entered) */
+    checkpoint: Cell<(usize, usize)>,
+    paused: Cell<bool>,
+}
+impl ReplayLog {
+    pub fn new(inner: Rc<dyn GameLogInner>) -> Self {
+        ReplayLog {
+            inner,
+            passed_on: 0.into(),
+            calls: 0.into(),
+            span_ids: vec![].into(),
+            spans_entered: 0.into(),
+            checkpoint: (0, 0).into(),
+            paused: false.into(),
+        }
+    }
+
+    /** For when the game starts being played again from the last checkpoint
+     * (or from the beginning, if there isn't one)
+     */
+    pub fn rewind(&self) {
+        let (calls, spans_entered) = self.checkpoint.get();
+        self.calls.set(calls);
+        self.spans_entered.set(spans_entered);
+        self.paused.set(false);
+    }
+
+    /** Makes rewinds come back to here rather than the beginning, eg because the
+     * game gets played from a snapshot of this point from now on. This needs to
+     * be in between turns, when there aren't any spans open.
+     */
+    pub fn checkpoint(&self) {
+        self.checkpoint
+            .set((self.calls.get(), self.spans_entered.get()));
+    }
+
+    /** Stops passing anything on until the next rewind, eg because the rest
+     * of this playthrough isn't what really happens
+     */
+    pub fn pause(&self) {
+        self.paused.set(true);
+    }
+
+    /** Whether this call is one the inner log hasn't seen yet */
+    fn is_new(&self) -> bool {
+        let call = self.calls.get();
+        self.calls.set(call + 1);
+        if self.paused.get() || call < self.passed_on.get() {
+            return false;
+        }
+        self.passed_on.set(call + 1);
+        true
+    }
+}
+impl GameLogInner for ReplayLog {
+    fn record(&self, event: GameEvent) {
+        if self.is_new() {
+            self.inner.record(event);
+        }
+    }
+
+    fn enter_span(&self, span_name: &'static str, data: SpanData) -> SpanId {
+        let index = self.spans_entered.get();
+        self.spans_entered.set(index + 1);
+        if self.is_new() {
+            let id = self.inner.enter_span(span_name, data);
+            self.span_ids.borrow_mut().push(id);
+        }
+        SpanId::new(index as u64)
+    }
+
+    fn exit_span(&self, id: SpanId) {
+        if self.is_new() {
+            let index = id.value() as usize;
+            self.inner.exit_span(self.span_ids.borrow()[index]);
+        }
+    }
+}
+
+/** One line of a `JsonLog` */
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    /** Which game this came from, when several games share an output */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    game: Option<u64>,
+    #[serde(flatten)]
+    record: JsonRecord<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonRecord<'a> {
+    Enter {
+        id: u64,
+        span: &'static str,
+        /** The span this one is inside, if any */
+        parent: Option<u64>,
+        data: SpanData<'a>,
+    },
+    Exit {
+        id: u64,
+        span: &'static str,
+    },
+    Event {
+        /** The innermost span open when this happened, if any */
+        parent: Option<u64>,
+        #[serde(flatten)]
+        event: &'a GameEvent,
+    },
+}
+
+/** Somewhere that `JsonLog`s for several games (possibly on different threads)
+ * can all write to. Each line gets written in one go while holding the lock,
+ * so lines from different games never get mixed together.
+ */
+pub type SharedOutput = Arc<Mutex<dyn Write + Send>>;
+
+/** Writes newline-delimited JSON, one object per event or span enter/exit.
+ *
+ * Span ids are only unique within one game, so when several games share an
+ * output (see `JsonLog::for_game`) every line says which game it came from.
+ */
+pub struct JsonLog {
+    out: SharedOutput,
+    game: Option<u64>,
+    open_spans: RefCell<Vec<(u64, &'static str)>>,
+    next_id: Cell<u64>,
+}
+impl JsonLog {
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        JsonLog {
+            out: Arc::new(Mutex::new(out)),
+            game: None,
+            open_spans: vec![].into(),
+            next_id: 1.into(),
+        }
+    }
+
+    pub fn stdout() -> Self {
+        JsonLog::new(std::io::stdout())
+    }
+
+    /** Logs one of several games going to the same output, tagging every line
+     * with `game` (usually the game's seed)
+     */
+    pub fn for_game(out: &SharedOutput, game: u64) -> Self {
+        JsonLog {
+            out: out.clone(),
+            game: Some(game),
+            open_spans: vec![].into(),
+            next_id: 1.into(),
+        }
+    }
+
+    fn parent(&self) -> Option<u64> {
+        self.open_spans.borrow().last().map(|(id, _)| *id)
+    }
+
+    fn write(&self, record: JsonRecord) {
+        let line = JsonLine {
+            game: self.game,
+            record,
+        };
+        let mut bytes = serde_json::to_vec(&line).expect("BUG: log records always serialize");
+        bytes.push(b'\n');
+        self.out
+            .lock()
+            .expect("another thread panicked while writing to the JSON log")
+            .write_all(&bytes)
+            .expect("couldn't write to the JSON log");
+    }
+}
+impl GameLogInner for JsonLog {
+    fn record(&self, event: GameEvent) {
+        self.write(JsonRecord::Event {
+            parent: self.parent(),
+            event: &event,
+        });
+    }
+
+    fn enter_span(&self, span: &'static str, data: SpanData) -> SpanId {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.write(JsonRecord::Enter {
+            id,
+            span,
+            parent: self.parent(),
+            data,
+        });
+        self.open_spans.borrow_mut().push((id, span));
+        SpanId::new(id)
+    }
+
+    fn exit_span(&self, id: SpanId) {
+        let (open_id, span) = self
+            .open_spans
+            .borrow_mut()
+            .pop()
+            .expect("BUG: exited a span that was never entered");
+        debug_assert!(id == SpanId::new(open_id));
+        self.write(JsonRecord::Exit { id: open_id, span });
+    }
+}
+impl Drop for JsonLog {
+    fn drop(&mut self) {
+        // there's nowhere left to report this, so at least don't panic during a drop
+        if let Ok(mut out) = self.out.lock() {
+            let _ = out.flush();
+        }
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
     use std::cell::RefCell;
 
-    #[derive(Debug)]
+    /** For tests that play games without looking at what happened in them */
+    pub fn null_log() -> GameLog {
+        GameLog::new(Rc::new(NullLog::new()))
+    }
+
+    #[derive(Debug, Default)]
     pub struct TestLog {
         messages: RefCell<Vec<String>>,
         indent: RefCell<u64>,
@@ -199,4 +527,83 @@ pub mod tests {
             *self.indent.borrow_mut() -= 1;
         }
     }
+
+    /** Somewhere for a `JsonLog` to write that the test can still read afterwards */
+    #[derive(Debug, Default, Clone)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    impl SharedBuffer {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+
+        fn lines(&self) -> Vec<serde_json::Value> {
+            self.text()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    #[test]
+    fn json_log_writes_a_line_for_each_span_and_event() {
+        use crate::game::cards::CardNames;
+
+        let buffer = SharedBuffer::default();
+        let log = GameLog::new(Rc::new(JsonLog::new(buffer.clone())));
+        {
+            let _turn = log.enter_turn("Player 1", 3);
+            let _repeat = log.enter_repeat(CardNames::SMITHY, 2);
+            log.record(GameEvent::CardPlayed(
+                CardNames::SMITHY,
+                PlayerCounters::new_turn(),
+            ));
+            log.record(GameEvent::UnaffectedByAttack);
+        }
+        log.record(GameEvent::GameEnded(GameEndReason::TurnLimitReached(100)));
+
+        let lines = buffer.lines();
+        insta::assert_snapshot!(buffer.text());
+        assert_eq!("Player 1", lines[0]["data"]["player_name"]);
+        assert_eq!(3, lines[0]["data"]["turn_counter"]);
+        assert_eq!(lines[0]["id"], lines[1]["parent"]);
+        assert_eq!(lines[1]["id"], lines[2]["parent"]);
+    }
+
+    #[test]
+    fn games_sharing_a_json_log_output_can_be_told_apart() {
+        use crate::game::cards::CardNames;
+
+        let buffer = SharedBuffer::default();
+        let out: SharedOutput = Arc::new(Mutex::new(buffer.clone()));
+        std::thread::scope(|scope| {
+            for game in 0..4 {
+                let out = &out;
+                scope.spawn(move || {
+                    let log = GameLog::new(Rc::new(JsonLog::for_game(out, game)));
+                    for turn in 1..=50 {
+                        let _turn = log.enter_turn("Player 1", turn);
+                        log.record(GameEvent::CardGained(CardNames::COPPER));
+                    }
+                });
+            }
+        });
+
+        let lines = buffer.lines();
+        assert_eq!(4 * 50 * 3, lines.len());
+        for game in 0..4 {
+            let ours = lines.iter().filter(|l| l["game"] == game).collect_vec();
+            assert_eq!(50 * 3, ours.len());
+            // span ids start again for each game, but still pair up within one
+            assert_eq!(ours[0]["id"], ours[1]["parent"]);
+            assert_eq!(ours[0]["id"], ours[2]["id"]);
+        }
+    }
 }
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+use super::{cards::CardName, player_counters::PlayerCounters};
+
+/** What the player being asked a question can see of the game at that point.
+ * This gets passed along with every `ChoiceRequest`, so that agents can make
+ * decisions based on more than the question itself, without needing access
+ * to the game (and without being able to see anything they shouldn't).
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Observation {
+    pub turn: u8,
+    /** Our own seat, counting from 0 */
+    pub seat: usize,
+    pub hand: Vec<CardName>,
+    /** We know what's in our deck, but not what order it's in, so this is sorted */
+    pub deck: Vec<CardName>,
+    pub discard: Vec<CardName>,
+    /** Including duration cards that are waiting for our next turn */
+    pub in_play: Vec<CardName>,
+    /** Our actions, buys and coins this turn. When we're being attacked on
+     * someone else's turn, these are just a fresh set that nothing uses.
+     */
+    pub counters: PlayerCounters,
+    /** In the order the piles were added to the supply */
+    pub supply: Vec<PileObservation>,
+    /** What everyone can see of each player (including us), in seating order */
+    pub players: Vec<PlayerObservation>,
+}
+
+impl Observation {
+    /** How many copies of `card` we have, wherever they are */
+    pub fn count_owned(&self, card: CardName) -> usize {
+        [&self.hand, &self.deck, &self.discard, &self.in_play]
+            .into_iter()
+            .flatten()
+            .filter(|&&c| c == card)
+            .count()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PileObservation {
+    /** The top card, or the card the pile started with once it's empty */
+    pub name: CardName,
+    /** None once the pile is empty */
+    pub cost: Option<u8>,
+    pub remaining: usize,
+}
+
+/** What anyone at the table can see of a player's cards */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerObservation {
+    pub seat: usize,
+    pub hand_size: usize,
+    pub deck_size: usize,
+    pub discard_size: usize,
+    pub in_play: Vec<CardName>,
+    /** Every card gained is public, so the score could be worked out anyway */
+    pub score: i16,
+}
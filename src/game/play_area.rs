@@ -1,130 +1,597 @@
+use itertools::Itertools;
+
 use super::{
     card_pile::{CardPile, DrawResult},
-    cards::{Card, CardName},
-    effects::CardEffect,
-    player_counters::PlayerCounters,
+    card_registry::{CardRegistry, CardRegistryError},
+    cards::{Card, CardName, CardNames, CardTypes},
+    effects::{CardEffect, TrashInstruction},
+    history::{Location, MoveReason, Place, StateChange, Zone},
+    observation::PlayerObservation,
+    players::{AgentQuestion, TurnPhase, TurnStateStackEntry},
+    snapshot::{names, print_cards, ZonesSnapshot},
+    turn_state::TurnState,
 };
 use crate::{
     game::logs::{GameEvent, GameLog},
-    game::shuffler::Shuffler,
+    game::shuffler::{Shuffler, ShufflerState},
 };
 
 #[derive(Debug)]
 pub struct PlayArea<'a> {
+    /** Which seat this belongs to, so that changes to it can say whose cards moved */
+    seat: usize,
     deck: CardPile,
     hand: Vec<Card>,
     in_play: Vec<Card>,
+    /** Duration cards (and anything that played them) which stay in play
+     * until the start of our next turn */
+    set_aside: Vec<Card>,
+    /** What those duration cards still have to do, and which card is doing it */
+    next_turn_effects: Vec<(CardName, CardEffect)>,
     discard: Vec<Card>,
+    /** Cards taken off the deck to look at, which always end up somewhere else
+     * before the effect that revealed them is done */
+    revealed: Vec<Card>,
+    /** The victory points of everything above, kept up to date as cards are
+     * gained and trashed, so that observing it doesn't mean counting them all */
+    score: i16,
     shuffler: &'a dyn Shuffler<Card>,
 }
 
 impl<'p> PlayArea<'p> {
-    pub fn new(shuffler: &'p dyn Shuffler<Card>) -> Self {
+    pub fn new(seat: usize, shuffler: &'p dyn Shuffler<Card>) -> Self {
         PlayArea {
+            seat,
             deck: CardPile::new(),
             hand: vec![],
             in_play: vec![],
+            set_aside: vec![],
+            next_turn_effects: vec![],
             discard: vec![],
+            revealed: vec![],
+            score: 0,
             shuffler,
         }
     }
 
+    pub fn snapshot(&self) -> ZonesSnapshot {
+        ZonesSnapshot {
+            deck: self.deck.iter().map(|c| c.name).collect(),
+            hand: names(&self.hand),
+            in_play: names(&self.in_play),
+            set_aside: names(&self.set_aside),
+            next_turn_effects: self.next_turn_effects.clone(),
+            discard: names(&self.discard),
+            revealed: names(&self.revealed),
+        }
+    }
+
+    pub fn shuffler_state(&self) -> Option<ShufflerState> {
+        self.shuffler.state()
+    }
+
+    pub fn restore(
+        seat: usize,
+        shuffler: &'p dyn Shuffler<Card>,
+        zones: &ZonesSnapshot,
+        registry: &CardRegistry,
+    ) -> Result<Self, CardRegistryError> {
+        let mut area = PlayArea {
+            seat,
+            deck: print_cards(registry, &zones.deck)?.into(),
+            hand: print_cards(registry, &zones.hand)?,
+            in_play: print_cards(registry, &zones.in_play)?,
+            set_aside: print_cards(registry, &zones.set_aside)?,
+            next_turn_effects: zones.next_turn_effects.clone(),
+            discard: print_cards(registry, &zones.discard)?,
+            revealed: print_cards(registry, &zones.revealed)?,
+            score: 0,
+            shuffler,
+        };
+        area.score = points(area.all_cards());
+        Ok(area)
+    }
+
     #[cfg(test)]
     pub fn test_from_hand(hand: Vec<Card>) -> Self {
         PlayArea {
+            seat: 0,
             deck: CardPile::new(),
+            score: points(&hand),
             hand,
             in_play: vec![],
+            set_aside: vec![],
+            next_turn_effects: vec![],
             discard: vec![],
+            revealed: vec![],
             shuffler: &crate::game::shuffler::NoShuffle,
         }
     }
 
     pub fn draw_n(&mut self, n: usize, log: &GameLog) {
+        self.take_from_deck(n, log, GameEvent::DrawCards, Zone::Hand);
+    }
+
+    /** Takes cards from the top of the deck into another zone, shuffling the
+     * discard pile if we run out. `describe` says what we're taking the cards for,
+     * so that we can log it.
+     */
+    fn take_from_deck(
+        &mut self,
+        n: usize,
+        log: &GameLog,
+        describe: fn(usize) -> GameEvent,
+        to: Zone,
+    ) {
         let cards = self.deck.take_n(n);
         match cards {
-            DrawResult::Complete(mut cards) => {
-                self.hand.append(&mut cards);
-                log.record(GameEvent::DrawCards(n));
+            DrawResult::Complete(cards) => {
+                log.record(describe(n));
+                self.put_from_deck(cards, to, log);
             }
-            DrawResult::Partial(mut cards, remaining) => {
-                log.record(GameEvent::DrawCards(cards.len()));
-                self.hand.append(&mut cards);
+            DrawResult::Partial(cards, remaining) => {
+                log.record(describe(cards.len()));
+                self.put_from_deck(cards, to, log);
                 // we didn't get all the cards we need, so shuffle the discard pile
                 // and turn it back into the deck:
                 assert!(self.deck.is_empty());
 
                 log.record(GameEvent::Shuffle());
+                let before = log
+                    .is_recording_changes()
+                    .then(|| (names(&self.discard), self.shuffler.state()));
                 let mut shuffled = self.shuffler.shuffle(&mut self.discard);
+                if let Some((discard, shuffler)) = before {
+                    log.change(StateChange::Shuffle {
+                        player: self.seat,
+                        discard,
+                        deck: names(&shuffled),
+                        shuffler: shuffler.zip(self.shuffler.state()),
+                    });
+                }
 
                 self.deck.add_range(&mut shuffled);
-                log.record(GameEvent::DrawCards(remaining));
-                let mut remaining_cards = self.deck.take_up_to_n(remaining);
-                self.hand.append(&mut remaining_cards)
+                log.record(describe(remaining));
+                let remaining_cards = self.deck.take_up_to_n(remaining);
+                self.put_from_deck(remaining_cards, to, log);
             }
         }
     }
 
+    /** `cards` were the top of the deck, in the order they were in there */
+    fn put_from_deck(&mut self, cards: Vec<Card>, to: Zone, log: &GameLog) {
+        let reason = match to {
+            Zone::Revealed => MoveReason::Reveal,
+            _ => MoveReason::Draw,
+        };
+        // the deck has already lost all of them, so each one is the next card
+        // after what's left of it
+        let from = self.location(Zone::Deck, self.deck.len());
+        for card in cards {
+            self.put(card, from, to, reason, log);
+        }
+    }
+
+    fn location(&self, zone: Zone, index: usize) -> Location {
+        Location::new(Place::Player(self.seat, zone), index)
+    }
+
+    /** Every zone apart from the deck, which is a pile rather than a Vec */
+    fn zone_mut(&mut self, zone: Zone) -> &mut Vec<Card> {
+        match zone {
+            Zone::Deck => panic!("BUG: cards only go into the deck by shuffling"),
+            Zone::Hand => &mut self.hand,
+            Zone::InPlay => &mut self.in_play,
+            Zone::SetAside => &mut self.set_aside,
+            Zone::Discard => &mut self.discard,
+            Zone::Revealed => &mut self.revealed,
+        }
+    }
+
+    /** Puts a card that's been taken from `from` at the end of one of our zones */
+    fn put(&mut self, card: Card, from: Location, to: Zone, reason: MoveReason, log: &GameLog) {
+        if log.is_recording_changes() {
+            let index = self.zone_mut(to).len();
+            log.change(StateChange::Move {
+                reason,
+                card: card.name,
+                from,
+                to: self.location(to, index),
+            });
+        }
+        self.zone_mut(to).push(card);
+    }
+
+    /** Moves everything in one zone to the end of another, keeping them in order */
+    fn move_all(&mut self, from: Zone, to: Zone, reason: MoveReason, log: &GameLog) {
+        for card in std::mem::take(self.zone_mut(from)) {
+            self.put(card, self.location(from, 0), to, reason, log);
+        }
+    }
+
+    /** Takes the first card with this name from a zone */
+    fn take_from(&mut self, zone: Zone, name: CardName) -> (Location, Card) {
+        let cards = self.zone_mut(zone);
+        let index = cards
+            .iter()
+            .position(|c| c.name == name)
+            .unwrap_or_else(|| panic!("BUG: expected {:?} to contain {:?}", zone, name));
+        let card = cards.remove(index);
+        (self.location(zone, index), card)
+    }
+
+    /** Takes a card that's about to be trashed, so it no longer counts for us */
+    fn take_to_trash(&mut self, zone: Zone, name: CardName) -> (Location, Card) {
+        let (from, card) = self.take_from(zone, name);
+        self.score -= i16::from(card.vp_value);
+        (from, card)
+    }
+
     pub fn draw_hand(&mut self, log: &GameLog) {
         self.draw_n(5, log)
     }
 
-    pub fn discard_hand(&mut self) {
-        self.discard.append(&mut self.hand);
+    pub fn discard_hand(&mut self, log: &GameLog) {
+        self.move_all(Zone::Hand, Zone::Discard, MoveReason::Cleanup, log);
     }
 
-    pub fn discard_in_play(&mut self) {
-        self.discard.append(&mut self.in_play);
+    /** Resolves anything that duration cards left for this turn. The cards
+     * themselves then count as played this turn, so they'll get cleaned up with
+     * everything else.
+     */
+    pub fn start_turn(&mut self, turn: &mut TurnState<'_, 'p>) {
+        // most turns have nothing to do here, so don't clutter up the log
+        if self.next_turn_effects.is_empty() {
+            return;
+        }
+
+        let _span = turn.log.enter_start_of_turn();
+        turn.push(TurnStateStackEntry::Phase(TurnPhase::StartOfTurn));
+        let effects = std::mem::take(&mut self.next_turn_effects);
+        if turn.log.is_recording_changes() {
+            turn.log.change(StateChange::NextTurnEffects {
+                player: self.seat,
+                before: effects.clone(),
+                after: vec![],
+            });
+        }
+        for (name, effect) in effects {
+            self.resolve_card(name, effect, turn);
+        }
+        turn.pop();
+        self.move_all(
+            Zone::SetAside,
+            Zone::InPlay,
+            MoveReason::StartOfTurn,
+            turn.log,
+        );
+    }
+
+    pub fn discard_in_play(&mut self, log: &GameLog) {
+        self.move_all(Zone::InPlay, Zone::Discard, MoveReason::Cleanup, log);
     }
 
+    /** For starting decks, which come from outside the game rather than from the supply */
     pub fn gain_cards_to_discard_pile(&mut self, cards: &mut Vec<Card>) {
+        self.score += points(cards.iter());
         self.discard.append(cards)
     }
 
-    pub fn gain_card_to_discard_pile(&mut self, card: Card) {
-        self.discard.push(card)
+    /** Puts a card that's been taken from the supply into our discard pile (or hand) */
+    pub fn gain_card(
+        &mut self,
+        card: Card,
+        from: Location,
+        reason: MoveReason,
+        to_hand: bool,
+        log: &GameLog,
+    ) {
+        let to = if to_hand { Zone::Hand } else { Zone::Discard };
+        self.score += i16::from(card.vp_value);
+        self.put(card, from, to, reason, log);
     }
 
     pub fn inspect_hand(&self) -> impl Iterator<Item = &Card> + '_ {
         self.hand.iter()
     }
 
-    pub fn play_card(&mut self, name: CardName, counters: &mut PlayerCounters, log: &GameLog) {
-        let card = self.hand.remove(
-            self.hand
-                .iter()
-                .position(|c| c.name == name)
-                .expect("BUG: expected hand to contain card being played"),
+    /** Duration cards that are set aside are still in play as far as anyone can tell */
+    pub fn inspect_in_play(&self) -> impl Iterator<Item = &Card> + '_ {
+        self.in_play.iter().chain(self.set_aside.iter())
+    }
+
+    pub fn inspect_deck(&self) -> impl Iterator<Item = &Card> + '_ {
+        self.deck.iter()
+    }
+
+    pub fn inspect_discard(&self) -> impl Iterator<Item = &Card> + '_ {
+        self.discard.iter()
+    }
+
+    pub fn seat(&self) -> usize {
+        self.seat
+    }
+
+    /** What the other players can see of our cards */
+    pub fn public_info(&self) -> PlayerObservation {
+        debug_assert_eq!(
+            points(self.all_cards()),
+            self.score,
+            "BUG: seat {}'s score is out of date",
+            self.seat
         );
+        PlayerObservation {
+            seat: self.seat,
+            hand_size: self.hand.len(),
+            deck_size: self.deck.len(),
+            discard_size: self.discard.len(),
+            in_play: self.inspect_in_play().map(|c| c.name).collect(),
+            score: self.score,
+        }
+    }
+
+    fn all_cards(&self) -> impl Iterator<Item = &Card> + '_ {
+        self.deck
+            .iter()
+            .chain(&self.hand)
+            .chain(&self.in_play)
+            .chain(&self.set_aside)
+            .chain(&self.discard)
+            .chain(&self.revealed)
+    }
+
+    fn move_from_hand(&mut self, name: CardName, to: Zone, reason: MoveReason, log: &GameLog) {
+        let (from, card) = self.take_from(Zone::Hand, name);
+        self.put(card, from, to, reason, log);
+    }
+
+    pub fn play_card(&mut self, name: CardName, turn: &mut TurnState<'_, 'p>) {
+        // the card goes into play before we resolve it, so that it's already
+        // there if its effect wants to do anything with it
+        let index = self.in_play.len();
+        self.move_from_hand(name, Zone::InPlay, MoveReason::Play, turn.log);
+        let effect = self.in_play[index].effect.clone();
+        let effects_before = self.next_turn_effects.len();
+
+        self.resolve_card(name, effect, turn);
+        self.resolve_first_play_bonuses(name, turn);
+        self.set_aside_if_still_needed(index, effects_before, turn.log);
+    }
+
+    /** eg Merchant giving +$1 for the first Silver played after it */
+    fn resolve_first_play_bonuses(&mut self, name: CardName, turn: &mut TurnState<'_, 'p>) {
+        for effect in turn.take_first_play_bonuses(name) {
+            self.resolve_card(name, effect, turn);
+        }
+    }
+
+    /** If resolving the card at `index` left anything to do next turn, then it
+     * needs to stay in play past cleanup. Resolving a card only ever adds cards
+     * after it, so `index` still points at the same card.
+     */
+    fn set_aside_if_still_needed(&mut self, index: usize, effects_before: usize, log: &GameLog) {
+        if self.next_turn_effects.len() > effects_before {
+            let card = self.in_play.remove(index);
+            let from = self.location(Zone::InPlay, index);
+            self.put(card, from, Zone::SetAside, MoveReason::SetAside, log);
+        }
+    }
+
+    fn resolve_card(&mut self, name: CardName, effect: CardEffect, turn: &mut TurnState<'_, 'p>) {
+        turn.push(TurnStateStackEntry::Card(name));
+        self.resolve_effect(effect, turn);
+        turn.pop();
+    }
+
+    /** Plays a card from hand, then resolves it again (while it stays in play)
+     * until it's been resolved `times` times.
+     */
+    fn play_card_repeatedly(&mut self, name: CardName, times: u8, turn: &mut TurnState<'_, 'p>) {
+        let _span = turn.log.enter_repeat(name, times);
+        let index = self.in_play.len();
+        self.move_from_hand(name, Zone::InPlay, MoveReason::Play, turn.log);
+        let effect = self.in_play[index].effect.clone();
+        let effects_before = self.next_turn_effects.len();
 
-        self.resolve_effect(card.effect.clone(), counters, log);
+        for _ in 0..times {
+            self.resolve_card(name, effect.clone(), turn);
+            self.resolve_first_play_bonuses(name, turn);
+            turn.log
+                .record(GameEvent::CardPlayed(name, turn.counters.clone()));
+        }
+        self.set_aside_if_still_needed(index, effects_before, turn.log);
+    }
 
-        self.in_play.push(card);
+    fn resolve_effect(&mut self, effect: CardEffect, turn: &mut TurnState<'_, 'p>) {
+        match effect {
+            // sequences are just a container for other effects,
+            // so they don't need their own entry in the turn state stack
+            CardEffect::Sequence(s) => s.iter().for_each(|e| self.resolve_effect(e.clone(), turn)),
+            _ => {
+                turn.push(TurnStateStackEntry::Effect(effect.clone()));
+                self.resolve_single_effect(effect, turn);
+                turn.pop();
+            }
+        }
     }
 
-    fn resolve_effect(&mut self, effect: CardEffect, counters: &mut PlayerCounters, log: &GameLog) {
+    fn resolve_single_effect(&mut self, effect: CardEffect, turn: &mut TurnState<'_, 'p>) {
         match effect {
             CardEffect::None => {}
-            CardEffect::Sequence(s) => s
-                .iter()
-                .for_each(|e| self.resolve_effect(e.clone(), counters, log)),
-            CardEffect::AddActions(a) => counters.actions += a,
-            // CardEffect::AddBuys(_) => todo!(),
-            CardEffect::AddCoins(c) => counters.coins += c,
-            CardEffect::DrawCards(n) => self.draw_n(n.into(), log),
-            // CardEffect::TrashCardsFromHand(_) => todo!(),
+            CardEffect::Sequence(_) => self.resolve_effect(effect, turn),
+            CardEffect::AddActions(a) => turn.counters.actions += a,
+            CardEffect::AddBuys(b) => turn.counters.buys += b,
+            CardEffect::AddCoins(c) => turn.counters.coins += c,
+            CardEffect::DrawCards(n) => self.draw_n(n.into(), turn.log),
+            CardEffect::GainCard(name) => self.gain_from_supply(name, false, turn),
+            CardEffect::DiscardDownTo(n) => {
+                let count = self.hand.len().saturating_sub(n.into());
+                let hand = self.hand.iter().map(|c| c.name).collect();
+                let chosen =
+                    turn.request_choice(self, AgentQuestion::DiscardCardsFromHand(hand, count));
+                for name in chosen.iter() {
+                    self.move_from_hand(*name, Zone::Discard, MoveReason::Discard, turn.log);
+                    turn.log.record(GameEvent::Discarded(*name));
+                }
+            }
+            CardEffect::TrashRevealedTreasure(n) => {
+                self.take_from_deck(n.into(), turn.log, GameEvent::RevealCards, Zone::Revealed);
+                let trashable = self
+                    .revealed
+                    .iter()
+                    .sorted_by_key(|c| c.coins_cost)
+                    .filter(|c| c.get_types().any(|t| t == CardTypes::TREASURE))
+                    .filter(|c| c.name != CardNames::COPPER)
+                    .map(|c| c.name)
+                    .collect();
+                let chosen =
+                    turn.request_choice(self, AgentQuestion::TrashRevealedTreasure(trashable));
+                for name in chosen.iter() {
+                    let (from, card) = self.take_to_trash(Zone::Revealed, *name);
+                    turn.trash_card(card, from);
+                }
+                let cheapest_first = self
+                    .revealed
+                    .iter()
+                    .sorted_by_key(|c| c.coins_cost)
+                    .map(|c| c.name)
+                    .collect_vec();
+                for name in cheapest_first {
+                    turn.log.record(GameEvent::Discarded(name));
+                    let (from, card) = self.take_from(Zone::Revealed, name);
+                    self.put(card, from, Zone::Discard, MoveReason::Discard, turn.log);
+                }
+            }
+            CardEffect::Attack(attack) => turn.attack_each_opponent(self, |area, attacked| {
+                area.resolve_effect((*attack).clone(), attacked)
+            }),
+            CardEffect::PlayActionMultipleTimes(times) => {
+                let playable_cards = self
+                    .hand
+                    .iter()
+                    .filter(|c| c.get_types().any(|t| t == CardTypes::ACTION))
+                    .map(|c| c.name)
+                    .collect();
+                let chosen = turn.request_choice(self, AgentQuestion::PlayAction(playable_cards));
+                if let Some(&name) = chosen.first() {
+                    self.play_card_repeatedly(name, times, turn);
+                }
+            }
+            CardEffect::DiscardAndDraw => {
+                let hand = self.hand.iter().map(|c| c.name).collect();
+                let chosen = turn.request_choice(self, AgentQuestion::DiscardToDraw(hand));
+                for name in chosen.iter() {
+                    self.move_from_hand(*name, Zone::Discard, MoveReason::Discard, turn.log);
+                    turn.log.record(GameEvent::Discarded(*name));
+                }
+                if !chosen.is_empty() {
+                    self.draw_n(chosen.len(), turn.log);
+                }
+            }
+            CardEffect::GainCardCostingUpTo(max_cost) => {
+                let gainable = turn
+                    .supply
+                    .available_cards(|c| c.coins_cost <= max_cost)
+                    .collect();
+                let chosen = turn.request_choice(self, AgentQuestion::GainCard(gainable));
+                for name in chosen.iter() {
+                    self.gain_from_supply(*name, false, turn);
+                }
+            }
+            CardEffect::Improve(instruction) => {
+                let is_allowed = |c: &Card| {
+                    !instruction.treasures_only || c.get_types().any(|t| t == CardTypes::TREASURE)
+                };
+                let trashable = self
+                    .hand
+                    .iter()
+                    .filter(|c| is_allowed(c))
+                    .map(|c| c.name)
+                    .collect();
+                let trash_instruction = TrashInstruction {
+                    min_cards_to_trash: if instruction.optional { 0 } else { 1 },
+                    max_cards_to_trash: 1,
+                };
+                let chosen = turn.request_choice(
+                    self,
+                    AgentQuestion::TrashCardsFromHand(trashable, trash_instruction),
+                );
+                if let Some(&name) = chosen.first() {
+                    let (from, card) = self.take_to_trash(Zone::Hand, name);
+                    let max_cost = card.coins_cost + instruction.max_extra_cost;
+                    turn.trash_card(card, from);
+
+                    let gainable = turn
+                        .supply
+                        .available_cards(|c| c.coins_cost <= max_cost && is_allowed(c))
+                        .collect();
+                    let gained = turn.request_choice(self, AgentQuestion::GainCard(gainable));
+                    for name in gained.iter() {
+                        self.gain_from_supply(*name, instruction.gain_to_hand, turn);
+                    }
+                }
+            }
+            CardEffect::BonusOnFirstPlay(name, effect) => {
+                // it only counts if it's the first time this turn
+                if self.in_play.iter().all(|c| c.name != name) {
+                    turn.add_first_play_bonus(name, *effect);
+                }
+            }
+            CardEffect::NextTurn(effect) => {
+                let name = turn
+                    .current_card()
+                    .expect("BUG: next turn effects should come from a card");
+                let before = turn
+                    .log
+                    .is_recording_changes()
+                    .then(|| self.next_turn_effects.clone());
+                self.next_turn_effects.push((name, *effect));
+                if let Some(before) = before {
+                    turn.log.change(StateChange::NextTurnEffects {
+                        player: self.seat,
+                        before,
+                        after: self.next_turn_effects.clone(),
+                    });
+                }
+            }
+            CardEffect::TrashCardsFromHand(instruction) => {
+                let trashable_cards = self.hand.iter().map(|c| c.name).collect();
+                let chosen = turn.request_choice(
+                    self,
+                    AgentQuestion::TrashCardsFromHand(trashable_cards, instruction),
+                );
+                for name in chosen.iter() {
+                    let (from, card) = self.take_to_trash(Zone::Hand, *name);
+                    turn.trash_card(card, from);
+                }
+            }
+        }
+    }
+
+    fn gain_from_supply(&mut self, name: CardName, to_hand: bool, turn: &mut TurnState<'_, 'p>) {
+        if let Some((from, card)) = turn.supply.take_from_pile(name) {
+            self.gain_card(card, from, MoveReason::Gain, to_hand, turn.log);
+            turn.log.record(GameEvent::CardGained(name));
         }
     }
 
     pub fn take_all_cards(&mut self) -> Vec<Card> {
+        self.score = 0;
         let mut res = vec![];
         res.append(&mut self.deck.take_all());
         res.append(&mut self.hand);
+        res.append(&mut self.in_play);
+        res.append(&mut self.set_aside);
         res.append(&mut self.discard);
+        res.append(&mut self.revealed);
         res
     }
 }
 
+fn points<'c>(cards: impl IntoIterator<Item = &'c Card>) -> i16 {
+    cards.into_iter().map(|c| i16::from(c.vp_value)).sum()
+}
+
 #[cfg(test)]
 mod tests {
     use itertools::Itertools;
@@ -134,7 +601,12 @@ mod tests {
     use crate::game::{
         cards::{CardNames, Cards},
         logs::tests::TestLog,
+        observation::Observation,
+        players::{Agent, Agent2, Agents, BuyChoice, PlayChoice},
         shuffler::NoShuffle,
+        supply::Supply,
+        trash::Trash,
+        turn_state::Opponent,
     };
 
     macro_rules! cards {
@@ -161,7 +633,7 @@ mod tests {
 
     fn from_initial_cards(mut cards: Vec<Card>) -> PlayArea<'static> {
         let shuffler = Box::leak(Box::new(NoShuffle::new()));
-        let mut area = PlayArea::new(shuffler);
+        let mut area = PlayArea::new(0, shuffler);
         area.gain_cards_to_discard_pile(&mut cards);
         area
     }
@@ -174,6 +646,74 @@ mod tests {
         GameLog::new(Rc::new(TestLog::new()))
     }
 
+    fn make_turn() -> TurnState<'static, 'static> {
+        make_turn_with(Agents::always_buy_copper())
+    }
+
+    fn make_turn_with(agent: impl Agent2 + 'static) -> TurnState<'static, 'static> {
+        let log = Box::leak(Box::new(make_log()));
+        let agent = Box::leak(Box::new(agent));
+        let supply = Box::leak(Box::new(Supply::new()));
+        let trash = Box::leak(Box::new(Trash::new()));
+        TurnState::new(log, agent, supply, trash, vec![], 1)
+    }
+
+    /** Always plays the first action it's offered */
+    #[derive(Debug)]
+    struct PlaysFirstAction;
+    impl Agent for PlaysFirstAction {
+        fn action_phase(&mut self, playable_cards: &[CardName], _: &Observation) -> PlayChoice {
+            match playable_cards.first() {
+                Some(&card) => PlayChoice::Play(card),
+                None => PlayChoice::None,
+            }
+        }
+        fn buy_phase(&mut self, _buyable_cards: &[CardName], _: &Observation) -> BuyChoice {
+            BuyChoice::None
+        }
+    }
+
+    /** Owns everything a turn borrows, so that tests can check on it afterwards */
+    struct TestTable {
+        testlog: Rc<TestLog>,
+        log: GameLog,
+        supply: Supply,
+        trash: Trash,
+        agent: Box<dyn Agent2>,
+        opponent_agent: Box<dyn Agent2>,
+    }
+    impl TestTable {
+        fn new() -> Self {
+            let testlog = Rc::new(TestLog::new());
+            TestTable {
+                log: GameLog::new(testlog.clone()),
+                testlog,
+                supply: Supply::new(),
+                trash: Trash::new(),
+                agent: Box::new(Agents::always_buy_copper()),
+                opponent_agent: Box::new(Agents::always_buy_copper()),
+            }
+        }
+
+        fn turn_against<'t>(
+            &'t mut self,
+            opponent: &'t mut PlayArea<'static>,
+        ) -> TurnState<'t, 'static> {
+            TurnState::new(
+                &self.log,
+                self.agent.as_mut(),
+                &mut self.supply,
+                &mut self.trash,
+                vec![Opponent::new(
+                    "Opponent",
+                    opponent,
+                    self.opponent_agent.as_mut(),
+                )],
+                1,
+            )
+        }
+    }
+
     #[test]
     fn drawn_cards_go_into_hand() {
         let mut play_area = from_initial_cards(standard_cards());
@@ -191,7 +731,7 @@ mod tests {
         let mut play_area = from_initial_cards(standard_cards());
 
         play_area.draw_hand(&make_log());
-        play_area.discard_hand();
+        play_area.discard_hand(&make_log());
 
         assert_eq!(0, play_area.inspect_hand().count());
     }
@@ -202,7 +742,7 @@ mod tests {
 
         // draw 5 and discard
         play_area.draw_hand(&make_log());
-        play_area.discard_hand();
+        play_area.discard_hand(&make_log());
         // attempt to draw another 5: get some of the original discarded cards
         play_area.draw_hand(&make_log());
 
@@ -224,32 +764,420 @@ mod tests {
     #[test]
     fn playing_treasure_increases_coins() {
         let mut play_area = PlayArea::test_from_hand(cards![copper 1; silver 1]);
-        let mut counters = PlayerCounters::new_turn();
+        let mut turn = make_turn();
 
-        play_area.play_card(CardNames::COPPER, &mut counters, &make_log());
-        assert_eq!(1, counters.coins);
-        play_area.play_card(CardNames::SILVER, &mut counters, &make_log());
-        assert_eq!(3, counters.coins);
+        play_area.play_card(CardNames::COPPER, &mut turn);
+        assert_eq!(1, turn.counters.coins);
+        play_area.play_card(CardNames::SILVER, &mut turn);
+        assert_eq!(3, turn.counters.coins);
     }
 
     #[test]
     fn playing_smithy_draws_more_cards() {
         let mut play_area = PlayArea::test_from_hand(cards![smithy 1]);
         play_area.gain_cards_to_discard_pile(&mut cards![copper 3]);
-        let mut counters = PlayerCounters::new_turn();
+        let mut turn = make_turn();
 
-        play_area.play_card(CardNames::SMITHY, &mut counters, &make_log());
+        play_area.play_card(CardNames::SMITHY, &mut turn);
         assert_eq!(3, play_area.inspect_hand().count());
     }
 
     #[test]
     fn playing_village_increases_actions() {
         let mut play_area = PlayArea::test_from_hand(cards![village 1]);
-        let mut counters = PlayerCounters::new_turn();
+        let mut turn = make_turn();
 
-        assert_eq!(1, counters.actions);
-        play_area.play_card(CardNames::VILLAGE, &mut counters, &make_log());
+        assert_eq!(1, turn.counters.actions);
+        play_area.play_card(CardNames::VILLAGE, &mut turn);
         // note these tests assume that play_area isn't responsible for decrementing actions
-        assert_eq!(3, counters.actions);
+        assert_eq!(3, turn.counters.actions);
+    }
+
+    #[test]
+    fn playing_festival_adds_buys_and_coins() {
+        let mut play_area = PlayArea::test_from_hand(cards![festival 1]);
+        let mut turn = make_turn();
+
+        play_area.play_card(CardNames::FESTIVAL, &mut turn);
+
+        assert_eq!(2, turn.counters.buys);
+        assert_eq!(2, turn.counters.coins);
+    }
+
+    #[test]
+    fn playing_chapel_trashes_the_cards_the_agent_picks() {
+        let mut play_area =
+            PlayArea::test_from_hand(cards![chapel 1; estate 2; copper 3; silver 1]);
+        let mut turn = make_turn_with(Agents::chapel_big_money());
+
+        play_area.play_card(CardNames::CHAPEL, &mut turn);
+
+        // chapel can only trash up to 4 cards, so one copper survives
+        assert_eq!(
+            names![COPPER 1; SILVER 1],
+            play_area.inspect_hand().map(|c| c.name).collect_vec()
+        );
+        assert_eq!(
+            names![ESTATE 2; COPPER 2],
+            turn.trash.inspect().map(|c| c.name).collect_vec()
+        );
+        // and the estates' points went with them
+        assert_eq!(0, play_area.public_info().score);
+    }
+
+    #[test]
+    fn playing_militia_makes_opponents_discard_down_to_three() {
+        let mut play_area = PlayArea::test_from_hand(cards![militia 1]);
+        let mut opponent = PlayArea::test_from_hand(cards![gold 1; copper 2; estate 2]);
+        let mut table = TestTable::new();
+        let mut turn = table.turn_against(&mut opponent);
+
+        play_area.play_card(CardNames::MILITIA, &mut turn);
+        assert_eq!(2, turn.counters.coins);
+
+        assert_eq!(
+            names![GOLD 1; COPPER 2],
+            opponent.inspect_hand().map(|c| c.name).collect_vec()
+        );
+        assert_eq!(
+            names![ESTATE 2],
+            opponent.discard.iter().map(|c| c.name).collect_vec()
+        );
+    }
+
+    #[test]
+    fn playing_witch_gives_opponents_a_curse() {
+        let mut play_area = PlayArea::test_from_hand(cards![witch 1]);
+        play_area.gain_cards_to_discard_pile(&mut cards![copper 2]);
+        let mut opponent = PlayArea::test_from_hand(cards![copper 5]);
+        let mut table = TestTable::new();
+        table.supply.add(cards![curse 10]);
+        let mut turn = table.turn_against(&mut opponent);
+
+        play_area.play_card(CardNames::WITCH, &mut turn);
+
+        assert_eq!(2, play_area.inspect_hand().count());
+        assert_eq!(
+            names![CURSE 1],
+            opponent.discard.iter().map(|c| c.name).collect_vec()
+        );
+    }
+
+    #[test]
+    fn witch_does_nothing_to_opponents_once_the_curses_run_out() {
+        let mut play_area = PlayArea::test_from_hand(cards![witch 1]);
+        let mut opponent = PlayArea::test_from_hand(cards![copper 5]);
+        let mut table = TestTable::new();
+        table.supply.add(cards![curse 1]);
+        table.supply.take_one(CardNames::CURSE);
+        let mut turn = table.turn_against(&mut opponent);
+
+        play_area.play_card(CardNames::WITCH, &mut turn);
+
+        assert!(opponent.discard.is_empty());
+        assert_eq!(Some(0), table.supply.pile_size(CardNames::CURSE));
+    }
+
+    #[test]
+    fn bandit_still_attacks_without_a_gold_pile() {
+        let mut play_area = PlayArea::test_from_hand(cards![bandit 1]);
+        let mut opponent = PlayArea::test_from_hand(vec![]);
+        opponent.gain_cards_to_discard_pile(&mut cards![silver 1; copper 1]);
+        let mut table = TestTable::new();
+        let mut turn = table.turn_against(&mut opponent);
+
+        play_area.play_card(CardNames::BANDIT, &mut turn);
+
+        assert!(play_area.discard.is_empty());
+        assert_eq!(
+            names![SILVER 1],
+            table.trash.inspect().map(|c| c.name).collect_vec()
+        );
+    }
+
+    #[test]
+    fn playing_bandit_gains_gold_and_trashes_an_opponents_treasure() {
+        let mut play_area = PlayArea::test_from_hand(cards![bandit 1]);
+        let mut opponent = PlayArea::test_from_hand(vec![]);
+        opponent.gain_cards_to_discard_pile(&mut cards![copper 1; silver 1; gold 1]);
+        let mut table = TestTable::new();
+        table.supply.add(cards![gold 10]);
+        let mut turn = table.turn_against(&mut opponent);
+
+        play_area.play_card(CardNames::BANDIT, &mut turn);
+
+        assert_eq!(
+            names![GOLD 1],
+            play_area.discard.iter().map(|c| c.name).collect_vec()
+        );
+        // the top two cards are silver and gold, and by default the agent gives up the cheaper one
+        assert_eq!(
+            names![SILVER 1],
+            table.trash.inspect().map(|c| c.name).collect_vec()
+        );
+        assert_eq!(
+            names![GOLD 1],
+            opponent.discard.iter().map(|c| c.name).collect_vec()
+        );
+        assert_eq!(
+            names![COPPER 1],
+            opponent
+                .deck
+                .take_all()
+                .iter()
+                .map(|c| c.name)
+                .collect_vec()
+        );
+    }
+
+    #[test]
+    fn revealing_moat_blocks_attacks() {
+        let mut play_area = PlayArea::test_from_hand(cards![militia 1]);
+        let mut opponent = PlayArea::test_from_hand(cards![moat 1; copper 2; estate 2]);
+        let mut table = TestTable::new();
+        let mut turn = table.turn_against(&mut opponent);
+
+        play_area.play_card(CardNames::MILITIA, &mut turn);
+
+        assert_eq!(5, opponent.inspect_hand().count());
+        insta::assert_snapshot!(table.testlog.dump());
+    }
+
+    #[test]
+    fn throne_room_plays_an_action_twice() {
+        let mut play_area = PlayArea::test_from_hand(cards![throne_room 1; smithy 1]);
+        play_area.gain_cards_to_discard_pile(&mut cards![copper 8]);
+        let mut turn = make_turn_with(PlaysFirstAction);
+
+        play_area.play_card(CardNames::THRONE_ROOM, &mut turn);
+
+        assert_eq!(6, play_area.inspect_hand().count());
+        assert_eq!(
+            names![THRONE_ROOM 1; SMITHY 1],
+            play_area.in_play.iter().map(|c| c.name).collect_vec()
+        );
+    }
+
+    #[test]
+    fn throne_room_does_nothing_without_another_action() {
+        let mut play_area = PlayArea::test_from_hand(cards![throne_room 1; copper 4]);
+        let mut turn = make_turn_with(PlaysFirstAction);
+
+        play_area.play_card(CardNames::THRONE_ROOM, &mut turn);
+
+        assert_eq!(4, play_area.inspect_hand().count());
+        assert_eq!(1, turn.counters.actions);
+    }
+
+    #[test]
+    fn throne_room_can_play_another_throne_room_twice() {
+        let mut play_area = PlayArea::test_from_hand(cards![throne_room 2; village 1; smithy 1]);
+        play_area.gain_cards_to_discard_pile(&mut cards![copper 10]);
+        let mut opponent = PlayArea::test_from_hand(vec![]);
+        let mut table = TestTable::new();
+        table.agent = Box::new(PlaysFirstAction);
+        let mut turn = table.turn_against(&mut opponent);
+
+        play_area.play_card(CardNames::THRONE_ROOM, &mut turn);
+
+        // the second throne room plays village twice, and then smithy twice
+        assert_eq!(5, turn.counters.actions);
+        assert_eq!(8, play_area.inspect_hand().count());
+        assert_eq!(
+            names![THRONE_ROOM 2; VILLAGE 1; SMITHY 1],
+            play_area.in_play.iter().map(|c| c.name).collect_vec()
+        );
+        insta::assert_snapshot!(table.testlog.dump());
+    }
+
+    #[test]
+    fn duration_cards_stay_in_play_until_their_next_turn_effect_resolves() {
+        let mut play_area = PlayArea::test_from_hand(cards![wharf 1]);
+        play_area.gain_cards_to_discard_pile(&mut cards![copper 10]);
+        let log = make_log();
+        let mut turn = make_turn();
+
+        play_area.play_card(CardNames::WHARF, &mut turn);
+        play_area.discard_in_play(&make_log());
+        play_area.discard_hand(&make_log());
+        play_area.draw_hand(&log);
+
+        assert_eq!(2, turn.counters.buys);
+        assert!(play_area.discard.iter().all(|c| c.name != CardNames::WHARF));
+        assert_eq!(
+            names![WHARF 1],
+            play_area.set_aside.iter().map(|c| c.name).collect_vec()
+        );
+
+        let mut next_turn = make_turn();
+        play_area.start_turn(&mut next_turn);
+
+        assert_eq!(7, play_area.inspect_hand().count());
+        assert_eq!(2, next_turn.counters.buys);
+        assert!(play_area.set_aside.is_empty());
+
+        play_area.discard_in_play(&make_log());
+        assert!(play_area.discard.iter().any(|c| c.name == CardNames::WHARF));
+    }
+
+    #[test]
+    fn throne_room_stays_in_play_with_a_duration_card_it_played() {
+        let mut play_area = PlayArea::test_from_hand(cards![throne_room 1; caravan 1; village 1]);
+        play_area.gain_cards_to_discard_pile(&mut cards![copper 10]);
+        let mut opponent = PlayArea::test_from_hand(vec![]);
+        let mut table = TestTable::new();
+        table.agent = Box::new(PlaysFirstAction);
+
+        {
+            let mut turn = table.turn_against(&mut opponent);
+            play_area.play_card(CardNames::THRONE_ROOM, &mut turn);
+            play_area.play_card(CardNames::VILLAGE, &mut turn);
+            play_area.discard_in_play(&make_log());
+        }
+
+        assert_eq!(
+            names![CARAVAN 1; THRONE_ROOM 1],
+            play_area.set_aside.iter().map(|c| c.name).collect_vec()
+        );
+        assert_eq!(
+            names![VILLAGE 1],
+            play_area.discard.iter().map(|c| c.name).collect_vec()
+        );
+
+        {
+            let mut turn = table.turn_against(&mut opponent);
+            play_area.start_turn(&mut turn);
+            assert_eq!(1, turn.counters.actions);
+        }
+
+        // caravan was played twice, so it draws twice more on top of last turn's cards
+        assert_eq!(5, play_area.inspect_hand().count());
+        insta::assert_snapshot!(table.testlog.dump());
+    }
+
+    #[test]
+    fn fishing_village_and_merchant_ship_give_coins_next_turn() {
+        let mut play_area = PlayArea::test_from_hand(cards![fishing_village 1; merchant_ship 1]);
+        let mut turn = make_turn();
+
+        play_area.play_card(CardNames::FISHING_VILLAGE, &mut turn);
+        play_area.play_card(CardNames::MERCHANT_SHIP, &mut turn);
+        assert_eq!(3, turn.counters.coins);
+
+        let mut next_turn = make_turn();
+        play_area.start_turn(&mut next_turn);
+        assert_eq!(3, next_turn.counters.coins);
+        assert_eq!(2, next_turn.counters.actions);
+    }
+
+    #[test]
+    fn cellar_discards_cards_and_draws_the_same_number() {
+        let mut play_area = PlayArea::test_from_hand(cards![cellar 1; estate 2; copper 2]);
+        play_area.deck.add_range(&mut cards![copper 3]);
+        let mut turn = make_turn();
+
+        play_area.play_card(CardNames::CELLAR, &mut turn);
+
+        assert_eq!(2, turn.counters.actions);
+        assert_eq!(
+            names![COPPER 4],
+            play_area.inspect_hand().map(|c| c.name).collect_vec()
+        );
+        assert_eq!(
+            names![ESTATE 2],
+            play_area.discard.iter().map(|c| c.name).collect_vec()
+        );
+    }
+
+    #[test]
+    fn workshop_gains_a_card_costing_up_to_four() {
+        let mut play_area = PlayArea::test_from_hand(cards![workshop 1]);
+        let mut opponent = PlayArea::test_from_hand(vec![]);
+        let mut table = TestTable::new();
+        table.agent = Box::new(Agents::simple_big_money());
+        table.supply.add(cards![silver 10]);
+        table.supply.add(cards![gold 10]);
+        let mut turn = table.turn_against(&mut opponent);
+
+        play_area.play_card(CardNames::WORKSHOP, &mut turn);
+
+        assert_eq!(
+            names![SILVER 1],
+            play_area.discard.iter().map(|c| c.name).collect_vec()
+        );
+    }
+
+    #[test]
+    fn remodel_trashes_a_card_and_gains_one_costing_up_to_two_more() {
+        let mut play_area = PlayArea::test_from_hand(cards![remodel 1; estate 1; silver 1]);
+        let mut opponent = PlayArea::test_from_hand(vec![]);
+        let mut table = TestTable::new();
+        table.agent = Box::new(Agents::chapel_big_money());
+        table.supply.add(cards![silver 10]);
+        table.supply.add(cards![gold 10]);
+        let mut turn = table.turn_against(&mut opponent);
+
+        play_area.play_card(CardNames::REMODEL, &mut turn);
+
+        assert_eq!(
+            names![SILVER 1],
+            play_area.inspect_hand().map(|c| c.name).collect_vec()
+        );
+        assert_eq!(
+            names![SILVER 1],
+            play_area.discard.iter().map(|c| c.name).collect_vec()
+        );
+        assert_eq!(
+            names![ESTATE 1],
+            table.trash.inspect().map(|c| c.name).collect_vec()
+        );
+    }
+
+    #[test]
+    fn mine_upgrades_a_treasure_into_hand() {
+        let mut play_area = PlayArea::test_from_hand(cards![mine 1; estate 1; copper 1]);
+        let mut opponent = PlayArea::test_from_hand(vec![]);
+        let mut table = TestTable::new();
+        table.agent = Box::new(Agents::chapel_big_money());
+        table.supply.add(cards![silver 10]);
+        table.supply.add(cards![gold 10]);
+        let mut turn = table.turn_against(&mut opponent);
+
+        play_area.play_card(CardNames::MINE, &mut turn);
+
+        // estates aren't treasures, so the copper goes even though estates are trashed first
+        assert_eq!(
+            names![ESTATE 1; SILVER 1],
+            play_area.inspect_hand().map(|c| c.name).collect_vec()
+        );
+        assert_eq!(
+            names![COPPER 1],
+            table.trash.inspect().map(|c| c.name).collect_vec()
+        );
+    }
+
+    #[test]
+    fn merchant_boosts_the_first_silver_played_after_it() {
+        let mut play_area = PlayArea::test_from_hand(cards![merchant 1; silver 2]);
+        play_area.gain_cards_to_discard_pile(&mut cards![copper 1]);
+        let mut turn = make_turn();
+
+        play_area.play_card(CardNames::MERCHANT, &mut turn);
+        play_area.play_card(CardNames::SILVER, &mut turn);
+        play_area.play_card(CardNames::SILVER, &mut turn);
+
+        assert_eq!(2, turn.counters.actions);
+        assert_eq!(5, turn.counters.coins);
+    }
+
+    #[test]
+    fn merchant_does_nothing_if_silver_was_already_played() {
+        let mut play_area = PlayArea::test_from_hand(cards![silver 1; merchant 1]);
+        let mut turn = make_turn();
+
+        play_area.play_card(CardNames::SILVER, &mut turn);
+        play_area.play_card(CardNames::MERCHANT, &mut turn);
+
+        assert_eq!(2, turn.counters.coins);
     }
 }
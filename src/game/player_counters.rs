@@ -1,4 +1,6 @@
-#[derive(Clone, PartialEq, Eq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PlayerCounters {
     pub actions: u8,
     pub buys: u8,
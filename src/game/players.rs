@@ -1,10 +1,13 @@
-use std::collections::HashMap;
+pub mod human;
+pub mod remote;
 
 use derive_more::Constructor;
+use serde::{Deserialize, Serialize};
 
 use super::{
     cards::{CardName, CardNames},
-    effects::CardEffect,
+    effects::{CardEffect, TrashInstruction},
+    observation::Observation,
 };
 
 pub enum BuyChoice {
@@ -17,54 +20,335 @@ pub enum PlayChoice {
     None,
 }
 
-/** An agent is a thing that decides what to do */
+/** An agent is a thing that decides what to do. Every decision comes with
+ * an `Observation` of what the agent can see of the game at the time.
+ */
 pub trait Agent: std::fmt::Debug {
-    fn action_phase(&mut self, playable_cards: &[CardName]) -> PlayChoice;
-    fn buy_phase(&mut self, buyable_cards: &[CardName]) -> BuyChoice;
+    fn action_phase(
+        &mut self,
+        playable_cards: &[CardName],
+        observation: &Observation,
+    ) -> PlayChoice;
+    fn buy_phase(&mut self, buyable_cards: &[CardName], observation: &Observation) -> BuyChoice;
+
+    /** By default we trash as little as we can get away with */
+    fn choose_cards_to_trash(
+        &mut self,
+        trashable_cards: &[CardName],
+        instruction: TrashInstruction,
+        _observation: &Observation,
+    ) -> Vec<CardName> {
+        trashable_cards
+            .iter()
+            .take(instruction.min_cards_to_trash.into())
+            .cloned()
+            .collect()
+    }
+
+    /** By default we get rid of the cards least likely to be useful this turn */
+    fn choose_cards_to_discard(
+        &mut self,
+        hand: &[CardName],
+        count: usize,
+        _observation: &Observation,
+    ) -> Vec<CardName> {
+        let mut remaining = hand.to_vec();
+        let mut chosen = vec![];
+        for p in &[
+            CardNames::CURSE,
+            CardNames::ESTATE,
+            CardNames::DUCHY,
+            CardNames::PROVINCE,
+            CardNames::COLONY,
+            CardNames::COPPER,
+        ] {
+            while chosen.len() < count {
+                match remaining.iter().position(|c| c == p) {
+                    Some(i) => chosen.push(remaining.remove(i)),
+                    None => break,
+                }
+            }
+        }
+        let shortfall = count.saturating_sub(chosen.len());
+        chosen.extend(remaining.into_iter().take(shortfall));
+        chosen
+    }
+
+    /** By default we reveal everything we can, since reactions are generally good for us */
+    fn choose_reactions_to_reveal(
+        &mut self,
+        reactions: &[CardName],
+        _observation: &Observation,
+    ) -> Vec<CardName> {
+        reactions.to_vec()
+    }
+
+    /** By default we cycle away cards that won't help us this turn */
+    fn choose_cards_to_draw_past(
+        &mut self,
+        hand: &[CardName],
+        _observation: &Observation,
+    ) -> Vec<CardName> {
+        hand.iter()
+            .filter(|c| {
+                [
+                    CardNames::CURSE,
+                    CardNames::ESTATE,
+                    CardNames::DUCHY,
+                    CardNames::PROVINCE,
+                    CardNames::COLONY,
+                ]
+                .contains(c)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /** By default we gain the most expensive card we can. This doesn't go
+     * through `buy_phase`, since agents might keep track of what they've bought.
+     */
+    fn choose_card_to_gain(
+        &mut self,
+        gainable_cards: &[CardName],
+        observation: &Observation,
+    ) -> CardName {
+        let cost = |card: &CardName| {
+            observation
+                .supply
+                .iter()
+                .find(|pile| pile.name == *card)
+                .and_then(|pile| pile.cost)
+        };
+        // (reversed so that ties go to the first option)
+        *gainable_cards
+            .iter()
+            .rev()
+            .max_by_key(|c| cost(c))
+            .expect("BUG: asked to gain one of no cards")
+    }
+
+    /** By default we let the cheapest card go (assuming the options are sorted by cost) */
+    fn choose_treasure_to_lose(
+        &mut self,
+        revealed: &[CardName],
+        _observation: &Observation,
+    ) -> CardName {
+        revealed[0]
+    }
 }
 
-trait Agent2 {
-    fn request_choice(&mut self, choice: &ChoiceRequest) -> ChoiceResponse;
+/** The protocol the game actually uses to talk to agents: every decision is a
+ * single request carrying the stack of what's currently happening in the turn,
+ * along with what the agent can see of the game.
+ *
+ * Anything implementing the simpler `Agent` trait gets this for free via the
+ * adapter impl below.
+ */
+pub trait Agent2: std::fmt::Debug {
+    fn request_choice(
+        &mut self,
+        request: &ChoiceRequest,
+        observation: &Observation,
+    ) -> ChoiceResponse;
 }
 
-#[allow(dead_code)]
-struct ChoiceResponse(Box<[CardName]>);
+impl<T: Agent + ?Sized> Agent2 for T {
+    fn request_choice(
+        &mut self,
+        request: &ChoiceRequest,
+        observation: &Observation,
+    ) -> ChoiceResponse {
+        match request.question() {
+            AgentQuestion::PlayAction(playable_cards) => {
+                match self.action_phase(playable_cards, observation) {
+                    PlayChoice::Play(card) => ChoiceResponse::one(card),
+                    PlayChoice::None => ChoiceResponse::none(),
+                }
+            }
+            AgentQuestion::BuyCard(buyable_cards) => {
+                match self.buy_phase(buyable_cards, observation) {
+                    BuyChoice::Buy(card) => ChoiceResponse::one(card),
+                    BuyChoice::None => ChoiceResponse::none(),
+                }
+            }
+            AgentQuestion::TrashCardsFromHand(trashable_cards, instruction) => ChoiceResponse(
+                self.choose_cards_to_trash(trashable_cards, *instruction, observation)
+                    .into(),
+            ),
+            AgentQuestion::DiscardCardsFromHand(hand, count) => ChoiceResponse(
+                self.choose_cards_to_discard(hand, *count, observation)
+                    .into(),
+            ),
+            AgentQuestion::TrashRevealedTreasure(revealed) => {
+                ChoiceResponse::one(self.choose_treasure_to_lose(revealed, observation))
+            }
+            AgentQuestion::RevealReactions(reactions) => ChoiceResponse(
+                self.choose_reactions_to_reveal(reactions, observation)
+                    .into(),
+            ),
+            AgentQuestion::DiscardToDraw(hand) => {
+                ChoiceResponse(self.choose_cards_to_draw_past(hand, observation).into())
+            }
+            AgentQuestion::GainCard(gainable_cards) => {
+                ChoiceResponse::one(self.choose_card_to_gain(gainable_cards, observation))
+            }
+        }
+    }
+}
+
+/** The cards an agent picked in response to a question (possibly none) */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChoiceResponse(pub Box<[CardName]>);
+impl ChoiceResponse {
+    pub fn none() -> Self {
+        ChoiceResponse(Box::new([]))
+    }
+    pub fn one(card: CardName) -> Self {
+        ChoiceResponse(Box::new([card]))
+    }
+}
 
 // eg Library might have a stack like this:
 //  Phase(ActionPhase) > Card(Library) > Effect(DrawCards(7)) > Question(DiscardQ(<card>))
 // to ask the agent to maybe discard a card and keep drawing
 
 // TODO: what about other choices? like "gain +1$ or +1 buy"
-// or Moat deciding whether to reveal your card or not (although there's not really any reason not to?)
-// those can probably just be extra values in a ChoiceResponse enum
-// I guess the main downside is that the request/responses are less well-typed?
-// would need to handle the cases where agents give invalid responses more explicitly.
-// or maybe just a few top-level methods like request_card_choice and request_effect_choice
+// for now every question is phrased as "pick between min and max of these cards",
+// which covers yes/no questions too (pick the card or don't)
+
+/** The last entry in the stack is always the question being asked */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChoiceRequest(Box<[TurnStateStackEntry]>);
+impl ChoiceRequest {
+    pub fn new(stack: &[TurnStateStackEntry], question: AgentQuestion) -> Self {
+        let mut entries = Vec::with_capacity(stack.len() + 1);
+        entries.extend_from_slice(stack);
+        entries.push(TurnStateStackEntry::Question(question));
+        ChoiceRequest(entries.into())
+    }
+
+    pub fn stack(&self) -> &[TurnStateStackEntry] {
+        &self.0
+    }
 
-#[allow(dead_code)]
-struct ChoiceRequest(Box<[TurnStateStackEntry]>);
+    pub fn question(&self) -> &AgentQuestion {
+        match self.0.last() {
+            Some(TurnStateStackEntry::Question(q)) => q,
+            _ => unreachable!("BUG: ChoiceRequest should always end with a question"),
+        }
+    }
 
-#[allow(dead_code)]
-enum TurnStateStackEntry {
+    pub fn validate(&self, response: &ChoiceResponse) -> Result<(), InvalidChoice> {
+        let question = self.question();
+        let (min, max) = question.bounds();
+        let given = response.0.len();
+        if given < min || given > max {
+            return Err(InvalidChoice::WrongNumberOfCards { given, min, max });
+        }
+
+        let mut remaining_options = question.options().to_vec();
+        for card in response.0.iter() {
+            match remaining_options.iter().position(|o| o == card) {
+                Some(i) => {
+                    remaining_options.swap_remove(i);
+                }
+                None => return Err(InvalidChoice::NotAnOption(*card)),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum InvalidChoice {
+    WrongNumberOfCards {
+        given: usize,
+        min: usize,
+        max: usize,
+    },
+    NotAnOption(CardName),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TurnStateStackEntry {
     Phase(TurnPhase),
     Card(CardName),
     Effect(CardEffect),
+    /** Something on another player's turn is being done to us */
+    Attacked,
     Question(AgentQuestion),
 }
 
-#[allow(dead_code)]
-enum TurnPhase {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TurnPhase {
+    /** Resolving effects left over from duration cards played last turn */
+    StartOfTurn,
     Buy,
     Action,
     Cleanup,
+    #[allow(dead_code)] // no night cards yet
     Night,
     // some way of marking this, but another player's turn (in case of attacks?)
     /* ... */
 }
 
-#[allow(dead_code)]
-enum AgentQuestion {
-    DiscardQ(CardName),
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AgentQuestion {
+    /** Pick an action card to play, or nothing to move on to the buy phase */
+    PlayAction(Box<[CardName]>),
+    /** Pick a card to buy, or nothing to end the buy phase */
+    BuyCard(Box<[CardName]>),
+    /** Pick some cards from hand to trash */
+    TrashCardsFromHand(Box<[CardName]>, TrashInstruction),
+    /** Pick exactly this many cards from hand to discard */
+    DiscardCardsFromHand(Box<[CardName]>, usize),
+    /** Pick one of these revealed treasures to trash */
+    TrashRevealedTreasure(Box<[CardName]>),
+    /** Pick any of these reaction cards from hand to reveal in response to an attack */
+    RevealReactions(Box<[CardName]>),
+    /** Pick any number of cards from hand to discard, to draw the same number */
+    DiscardToDraw(Box<[CardName]>),
+    /** Pick one of these cards to gain from the supply */
+    GainCard(Box<[CardName]>),
+}
+
+impl AgentQuestion {
+    pub fn options(&self) -> &[CardName] {
+        match self {
+            AgentQuestion::PlayAction(cards) => cards,
+            AgentQuestion::BuyCard(cards) => cards,
+            AgentQuestion::TrashCardsFromHand(cards, _) => cards,
+            AgentQuestion::DiscardCardsFromHand(cards, _) => cards,
+            AgentQuestion::TrashRevealedTreasure(cards) => cards,
+            AgentQuestion::RevealReactions(cards) => cards,
+            AgentQuestion::DiscardToDraw(cards) => cards,
+            AgentQuestion::GainCard(cards) => cards,
+        }
+    }
+
+    /** The minimum and maximum number of cards the agent can pick */
+    pub fn bounds(&self) -> (usize, usize) {
+        match self {
+            AgentQuestion::PlayAction(_) => (0, 1),
+            AgentQuestion::BuyCard(_) => (0, 1),
+            // if we don't have enough cards to trash, then we just trash everything
+            AgentQuestion::TrashCardsFromHand(cards, instruction) => (
+                cards.len().min(instruction.min_cards_to_trash.into()),
+                instruction.max_cards_to_trash.into(),
+            ),
+            AgentQuestion::DiscardCardsFromHand(_, count) => (*count, *count),
+            AgentQuestion::TrashRevealedTreasure(_) => (1, 1),
+            AgentQuestion::RevealReactions(cards) => (0, cards.len()),
+            AgentQuestion::DiscardToDraw(cards) => (0, cards.len()),
+            AgentQuestion::GainCard(_) => (1, 1),
+        }
+    }
+
+    /** What we use if the agent gives us something we can't accept */
+    pub fn default_response(&self) -> ChoiceResponse {
+        let (min, _) = self.bounds();
+        ChoiceResponse(self.options().iter().take(min).cloned().collect())
+    }
 }
 
 #[derive(Debug, Constructor)]
@@ -73,7 +357,7 @@ struct BasicPriorities {
     play_priorities: Vec<CardName>,
 }
 impl Agent for BasicPriorities {
-    fn action_phase(&mut self, playable_cards: &[CardName]) -> PlayChoice {
+    fn action_phase(&mut self, playable_cards: &[CardName], _: &Observation) -> PlayChoice {
         for p in &self.play_priorities {
             if playable_cards.iter().any(|c| c == p) {
                 return PlayChoice::Play(*p);
@@ -81,7 +365,7 @@ impl Agent for BasicPriorities {
         }
         PlayChoice::None
     }
-    fn buy_phase(&mut self, buyable_cards: &[CardName]) -> BuyChoice {
+    fn buy_phase(&mut self, buyable_cards: &[CardName], _: &Observation) -> BuyChoice {
         for p in &self.buy_priorities {
             if buyable_cards.iter().any(|c| c == p) {
                 return BuyChoice::Buy(*p);
@@ -89,25 +373,42 @@ impl Agent for BasicPriorities {
         }
         BuyChoice::None
     }
+
+    /** Gaining goes by the same priorities as buying */
+    fn choose_card_to_gain(
+        &mut self,
+        gainable_cards: &[CardName],
+        observation: &Observation,
+    ) -> CardName {
+        match self.buy_phase(gainable_cards, observation) {
+            BuyChoice::Buy(card) => card,
+            BuyChoice::None => gainable_cards[0],
+        }
+    }
 }
 
-#[derive(Debug)]
+/** Like `BasicPriorities`, but stops buying each card once it has enough of them */
+#[derive(Debug, Constructor)]
 struct BoundedPriorities {
     buy_priorities: Vec<(CardName, u8)>,
     play_priorities: Vec<CardName>,
-    purchased_cards: HashMap<CardName, u8>,
+    trash_priorities: Vec<CardName>,
 }
 impl BoundedPriorities {
-    fn new(buy_priorities: Vec<(CardName, u8)>, play_priorities: Vec<CardName>) -> Self {
-        Self {
-            buy_priorities,
-            play_priorities,
-            purchased_cards: HashMap::<CardName, u8>::new(),
-        }
+    /** The first card in our priorities that's on offer and that we don't have enough of yet */
+    fn most_wanted(&self, cards: &[CardName], observation: &Observation) -> Option<CardName> {
+        self.buy_priorities
+            .iter()
+            // counting means looking through all our cards, so only bother for ones on offer
+            .find(|(candidate, max_we_want)| {
+                cards.contains(candidate)
+                    && observation.count_owned(*candidate) < usize::from(*max_we_want)
+            })
+            .map(|(candidate, _)| *candidate)
     }
 }
 impl Agent for BoundedPriorities {
-    fn action_phase(&mut self, playable_cards: &[CardName]) -> PlayChoice {
+    fn action_phase(&mut self, playable_cards: &[CardName], _: &Observation) -> PlayChoice {
         for p in &self.play_priorities {
             if playable_cards.iter().any(|c| c == p) {
                 return PlayChoice::Play(*p);
@@ -116,23 +417,81 @@ impl Agent for BoundedPriorities {
         PlayChoice::None
     }
 
-    fn buy_phase(&mut self, buyable_cards: &[CardName]) -> BuyChoice {
-        for (candidate, max_we_want) in &self.buy_priorities {
-            let already_gained_count = self.purchased_cards.entry(*candidate).or_insert(0);
-            if *already_gained_count >= *max_we_want {
-                continue;
-            }
-            if buyable_cards.iter().any(|c| c == candidate) {
-                *already_gained_count += 1;
-                return BuyChoice::Buy(*candidate);
+    fn buy_phase(&mut self, buyable_cards: &[CardName], observation: &Observation) -> BuyChoice {
+        match self.most_wanted(buyable_cards, observation) {
+            Some(card) => BuyChoice::Buy(card),
+            None => BuyChoice::None,
+        }
+    }
+
+    /** Gaining goes by the same priorities and limits as buying */
+    fn choose_card_to_gain(
+        &mut self,
+        gainable_cards: &[CardName],
+        observation: &Observation,
+    ) -> CardName {
+        self.most_wanted(gainable_cards, observation)
+            .unwrap_or(gainable_cards[0])
+    }
+
+    fn choose_cards_to_trash(
+        &mut self,
+        trashable_cards: &[CardName],
+        instruction: TrashInstruction,
+        _: &Observation,
+    ) -> Vec<CardName> {
+        let mut remaining = trashable_cards.to_vec();
+        let mut chosen = vec![];
+        for p in &self.trash_priorities {
+            while chosen.len() < instruction.max_cards_to_trash.into() {
+                match remaining.iter().position(|c| c == p) {
+                    Some(i) => chosen.push(remaining.remove(i)),
+                    None => break,
+                }
             }
         }
-        BuyChoice::None
+        // if we're forced to trash more than we want to, then just pick whatever
+        let min = instruction.min_cards_to_trash.into();
+        if chosen.len() < min {
+            let shortfall = min - chosen.len();
+            chosen.extend(remaining.into_iter().take(shortfall));
+        }
+        chosen
     }
 }
 
 pub struct Agents {}
 impl Agents {
+    /** Everything `by_name` knows how to make */
+    pub const NAMES: &'static [&'static str] = &[
+        "always_buy_copper",
+        "greedy_for_duchies",
+        "simple_big_money",
+        "big_money_splash_smithys",
+        "big_money_witch",
+        "chapel_big_money",
+    ];
+
+    pub fn by_name(name: &str) -> Option<Box<dyn Agent2>> {
+        let agent: Box<dyn Agent2> = match name {
+            "always_buy_copper" => Box::new(Agents::always_buy_copper()),
+            "greedy_for_duchies" => Box::new(Agents::greedy_for_duchies()),
+            "simple_big_money" => Box::new(Agents::simple_big_money()),
+            "big_money_splash_smithys" => Box::new(Agents::big_money_splash_smithys()),
+            "big_money_witch" => Box::new(Agents::big_money_witch()),
+            "chapel_big_money" => Box::new(Agents::chapel_big_money()),
+            _ => return None,
+        };
+        Some(agent)
+    }
+
+    /** Buys (and plays) whichever card comes first in each list, so that
+     * agents can be put together from card names that aren't known until runtime
+     */
+    #[allow(dead_code)]
+    pub fn priorities(buy_priorities: Vec<CardName>, play_priorities: Vec<CardName>) -> impl Agent {
+        BasicPriorities::new(buy_priorities, play_priorities)
+    }
     #[allow(dead_code)]
     pub fn always_buy_copper() -> impl Agent {
         BasicPriorities::new(vec![CardNames::COPPER], vec![])
@@ -154,6 +513,13 @@ impl Agents {
         BasicPriorities::new(vec![CardNames::PROVINCE, CardNames::SILVER], vec![])
     }
     #[allow(dead_code)]
+    pub fn woodcutter_test() -> impl Agent {
+        BasicPriorities::new(
+            vec![CardNames::WOODCUTTER, CardNames::COPPER],
+            vec![CardNames::WOODCUTTER],
+        )
+    }
+    #[allow(dead_code)]
     pub fn simple_big_money() -> impl Agent {
         BasicPriorities::new(
             vec![CardNames::PROVINCE, CardNames::GOLD, CardNames::SILVER],
@@ -171,6 +537,188 @@ impl Agents {
                 (CardNames::SILVER, 100),
             ],
             vec![CardNames::VILLAGE, CardNames::SMITHY],
+            vec![],
         )
     }
+    #[allow(dead_code)]
+    pub fn big_money_witch() -> impl Agent {
+        BoundedPriorities::new(
+            vec![
+                (CardNames::PROVINCE, 100),
+                (CardNames::WITCH, 2),
+                (CardNames::GOLD, 100),
+                (CardNames::SILVER, 100),
+            ],
+            vec![CardNames::WITCH],
+            vec![],
+        )
+    }
+    #[allow(dead_code)]
+    pub fn chapel_big_money() -> impl Agent {
+        BoundedPriorities::new(
+            vec![
+                (CardNames::PROVINCE, 100),
+                (CardNames::GOLD, 100),
+                (CardNames::CHAPEL, 1),
+                (CardNames::SILVER, 100),
+            ],
+            vec![CardNames::CHAPEL],
+            vec![CardNames::ESTATE, CardNames::COPPER],
+        )
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::game::{
+        kingdom::Kingdom, observation::PileObservation, player_counters::PlayerCounters,
+    };
+
+    /** Buys a bit of everything, so every card in the kingdom gets played at some point */
+    pub fn busy_agent(kingdom: &Kingdom) -> impl Agent2 {
+        let buy = [CardNames::PROVINCE, CardNames::GOLD]
+            .into_iter()
+            .chain(kingdom.cards.iter().rev().cloned())
+            .chain([CardNames::SILVER])
+            .collect_vec();
+        Agents::priorities(buy, kingdom.cards.clone())
+    }
+
+    #[test]
+    fn every_named_agent_can_be_made() {
+        for name in Agents::NAMES {
+            assert!(Agents::by_name(name).is_some(), "{}", name);
+        }
+        assert!(Agents::by_name("nobody").is_none());
+    }
+
+    fn buy_request(options: &[CardName]) -> ChoiceRequest {
+        ChoiceRequest::new(
+            &[TurnStateStackEntry::Phase(TurnPhase::Buy)],
+            AgentQuestion::BuyCard(options.into()),
+        )
+    }
+
+    #[test]
+    fn choosing_one_of_the_options_is_valid() {
+        let request = buy_request(&[CardNames::COPPER, CardNames::SILVER]);
+
+        assert_eq!(
+            Ok(()),
+            request.validate(&ChoiceResponse::one(CardNames::SILVER))
+        );
+        assert_eq!(Ok(()), request.validate(&ChoiceResponse::none()));
+    }
+
+    #[test]
+    fn choosing_something_that_was_not_offered_is_invalid() {
+        let request = buy_request(&[CardNames::COPPER, CardNames::SILVER]);
+
+        assert_eq!(
+            Err(InvalidChoice::NotAnOption(CardNames::GOLD)),
+            request.validate(&ChoiceResponse::one(CardNames::GOLD))
+        );
+    }
+
+    #[test]
+    fn choosing_too_many_cards_is_invalid() {
+        let request = buy_request(&[CardNames::COPPER, CardNames::SILVER]);
+
+        assert_eq!(
+            Err(InvalidChoice::WrongNumberOfCards {
+                given: 2,
+                min: 0,
+                max: 1
+            }),
+            request.validate(&ChoiceResponse(Box::new([
+                CardNames::COPPER,
+                CardNames::SILVER
+            ])))
+        );
+    }
+
+    #[test]
+    fn the_question_is_the_top_of_the_stack() {
+        let request = buy_request(&[CardNames::COPPER]);
+
+        assert_eq!(
+            &AgentQuestion::BuyCard(Box::new([CardNames::COPPER])),
+            request.question()
+        );
+        assert_eq!(2, request.stack().len());
+    }
+
+    /** What an agent would see if it had these cards in its deck */
+    fn owning(deck: Vec<CardName>) -> Observation {
+        Observation {
+            turn: 1,
+            seat: 0,
+            hand: vec![],
+            deck,
+            discard: vec![],
+            in_play: vec![],
+            counters: PlayerCounters::new_turn(),
+            supply: vec![],
+            players: vec![],
+        }
+    }
+
+    #[test]
+    fn bounded_priorities_stop_buying_once_they_have_enough() {
+        let mut agent = Agents::big_money_splash_smithys();
+        let buyable = [CardNames::SMITHY, CardNames::SILVER];
+
+        let one = owning(vec![CardNames::SMITHY, CardNames::COPPER]);
+        let two = owning(vec![CardNames::SMITHY, CardNames::SMITHY]);
+
+        assert!(matches!(
+            agent.buy_phase(&buyable, &one),
+            BuyChoice::Buy(CardNames::SMITHY)
+        ));
+        assert!(matches!(
+            agent.buy_phase(&buyable, &two),
+            BuyChoice::Buy(CardNames::SILVER)
+        ));
+    }
+
+    #[derive(Debug)]
+    struct OnlyGains;
+    impl Agent for OnlyGains {
+        fn action_phase(&mut self, _: &[CardName], _: &Observation) -> PlayChoice {
+            PlayChoice::None
+        }
+        fn buy_phase(&mut self, _: &[CardName], _: &Observation) -> BuyChoice {
+            panic!("gaining a card shouldn't count as buying it")
+        }
+    }
+
+    #[test]
+    fn by_default_agents_gain_the_most_expensive_card() {
+        let pile = |name, cost| PileObservation {
+            name,
+            cost: Some(cost),
+            remaining: 10,
+        };
+        let mut observation = owning(vec![]);
+        observation.supply = vec![
+            pile(CardNames::COPPER, 0),
+            pile(CardNames::SILVER, 3),
+            pile(CardNames::VILLAGE, 3),
+            pile(CardNames::ESTATE, 2),
+        ];
+        let gainable = [
+            CardNames::COPPER,
+            CardNames::VILLAGE,
+            CardNames::SILVER,
+            CardNames::ESTATE,
+        ];
+
+        assert_eq!(
+            CardNames::VILLAGE,
+            OnlyGains.choose_card_to_gain(&gainable, &observation)
+        );
+    }
 }
@@ -0,0 +1,364 @@
+use std::io::{self, BufRead, StdinLock, Stdout, Write};
+
+use itertools::Itertools;
+
+use crate::game::{
+    cards::CardName,
+    observation::Observation,
+    players::{
+        Agent2, AgentQuestion, ChoiceRequest, ChoiceResponse, InvalidChoice, TurnStateStackEntry,
+    },
+};
+
+/** Lets a person play, by showing them what they can see of the game and
+ * reading their choices back in.
+ *
+ * Choices can be typed as option numbers or card names, separated by commas,
+ * and a blank line picks nothing. If the input runs out, every remaining
+ * question gets its default answer so that the game can still finish.
+ */
+#[derive(Debug)]
+pub struct HumanAgent<R, W> {
+    input: R,
+    output: W,
+}
+
+impl HumanAgent<StdinLock<'static>, Stdout> {
+    /** Plays at the terminal. The game log goes to stdout too, which shows
+     * what the other players are up to in between our choices.
+     */
+    pub fn stdio() -> Self {
+        HumanAgent::new(io::stdin().lock(), io::stdout())
+    }
+}
+
+impl<R: BufRead, W: Write> HumanAgent<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        HumanAgent { input, output }
+    }
+
+    fn show(&mut self, request: &ChoiceRequest, observation: &Observation) -> io::Result<()> {
+        let out = &mut self.output;
+        writeln!(out)?;
+        writeln!(out, "=== Turn {} ===", observation.turn)?;
+        let supply = observation
+            .supply
+            .iter()
+            .map(|pile| match pile.cost {
+                Some(cost) => format!("{} ${} ({})", pile.name.as_str(), cost, pile.remaining),
+                None => format!("{} (empty)", pile.name.as_str()),
+            })
+            .join(", ");
+        writeln!(out, "Supply:  {}", supply)?;
+        writeln!(out, "In play: {}", list(&observation.in_play))?;
+        writeln!(out, "Hand:    {}", list(&observation.hand))?;
+        writeln!(
+            out,
+            "Deck:    {} cards  Discard: {} cards",
+            observation.deck.len(),
+            observation.discard.len()
+        )?;
+        let scores = observation
+            .players
+            .iter()
+            .map(|p| match p.seat == observation.seat {
+                true => format!("you {}", p.score),
+                false => format!("seat {} {}", p.seat + 1, p.score),
+            })
+            .join(", ");
+        writeln!(out, "Scores:  {}", scores)?;
+        let counters = &observation.counters;
+        writeln!(
+            out,
+            "Actions: {}  Buys: {}  Coins: ${}",
+            counters.actions, counters.buys, counters.coins
+        )?;
+
+        writeln!(out)?;
+        if request.stack().contains(&TurnStateStackEntry::Attacked) {
+            write!(out, "You're being attacked! ")?;
+        }
+        let card = request.stack().iter().rev().find_map(|e| match e {
+            TurnStateStackEntry::Card(name) => Some(name),
+            _ => None,
+        });
+        if let Some(card) = card {
+            write!(out, "{}: ", card.as_str())?;
+        }
+        let question = request.question();
+        writeln!(
+            out,
+            "{} ({})",
+            describe(question),
+            how_many(question.bounds())
+        )?;
+        for (i, option) in question.options().iter().enumerate() {
+            match cost(observation, *option) {
+                Some(cost) => writeln!(out, "  {}) {} ${}", i + 1, option.as_str(), cost)?,
+                None => writeln!(out, "  {}) {}", i + 1, option.as_str())?,
+            }
+        }
+        Ok(())
+    }
+
+    /** None if there's nothing left to read */
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        write!(self.output, "> ")?;
+        self.output.flush()?;
+        let mut line = String::new();
+        match self.input.read_line(&mut line)? {
+            0 => Ok(None),
+            _ => Ok(Some(line)),
+        }
+    }
+
+    fn ask(
+        &mut self,
+        request: &ChoiceRequest,
+        observation: &Observation,
+    ) -> io::Result<Option<ChoiceResponse>> {
+        self.show(request, observation)?;
+        let question = request.question();
+        loop {
+            let Some(line) = self.read_line()? else {
+                return Ok(None);
+            };
+            let problem = match parse(&line, question.options()) {
+                Ok(response) => match request.validate(&response) {
+                    Ok(()) => return Ok(Some(response)),
+                    Err(e) => explain(&e),
+                },
+                Err(problem) => problem,
+            };
+            writeln!(self.output, "{}, try again", problem)?;
+        }
+    }
+}
+
+impl<R: BufRead + std::fmt::Debug, W: Write + std::fmt::Debug> Agent2 for HumanAgent<R, W> {
+    fn request_choice(
+        &mut self,
+        request: &ChoiceRequest,
+        observation: &Observation,
+    ) -> ChoiceResponse {
+        match self.ask(request, observation) {
+            Ok(Some(response)) => response,
+            // there's nobody left to ask
+            Ok(None) | Err(_) => request.question().default_response(),
+        }
+    }
+}
+
+fn list(cards: &[CardName]) -> String {
+    if cards.is_empty() {
+        return "(nothing)".to_owned();
+    }
+    cards.iter().map(|c| c.as_str()).join(", ")
+}
+
+fn cost(observation: &Observation, card: CardName) -> Option<u8> {
+    observation
+        .supply
+        .iter()
+        .find(|pile| pile.name == card)
+        .and_then(|pile| pile.cost)
+}
+
+fn describe(question: &AgentQuestion) -> &'static str {
+    match question {
+        AgentQuestion::PlayAction(_) => "Play an action, or nothing to move on to buying",
+        AgentQuestion::BuyCard(_) => "Buy a card, or nothing to end your turn",
+        AgentQuestion::TrashCardsFromHand(_, _) => "Trash cards from your hand",
+        AgentQuestion::DiscardCardsFromHand(_, _) => "Discard cards from your hand",
+        AgentQuestion::TrashRevealedTreasure(_) => "Trash one of the revealed treasures",
+        AgentQuestion::RevealReactions(_) => "Reveal reactions from your hand",
+        AgentQuestion::DiscardToDraw(_) => "Discard cards to draw as many again",
+        AgentQuestion::GainCard(_) => "Gain a card",
+    }
+}
+
+fn how_many((min, max): (usize, usize)) -> String {
+    match (min, max) {
+        (0, 1) => "pick one or none".to_owned(),
+        (0, max) => format!("pick up to {}", max),
+        (min, max) if min == max => format!("pick {}", min),
+        (min, max) => format!("pick {} to {}", min, max),
+    }
+}
+
+fn explain(invalid: &InvalidChoice) -> String {
+    match invalid {
+        InvalidChoice::WrongNumberOfCards { given, min, max } => format!(
+            "that's {} cards, but you need to {}",
+            given,
+            how_many((*min, *max))
+        ),
+        InvalidChoice::NotAnOption(card) => {
+            format!("there aren't that many {}s to pick", card.as_str())
+        }
+    }
+}
+
+/** Each comma-separated choice can be an option number or a card name */
+fn parse(line: &str, options: &[CardName]) -> Result<ChoiceResponse, String> {
+    let mut chosen = vec![];
+    for choice in line.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        let card = match choice.parse::<usize>() {
+            Ok(number) => number
+                .checked_sub(1)
+                .and_then(|i| options.get(i))
+                .copied()
+                .ok_or_else(|| format!("there's no option {}", number))?,
+            Err(_) => options
+                .iter()
+                .find(|o| o.as_str().eq_ignore_ascii_case(choice))
+                .copied()
+                .ok_or_else(|| format!("{:?} isn't one of the options", choice))?,
+        };
+        chosen.push(card);
+    }
+    Ok(ChoiceResponse(chosen.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        cards::CardNames,
+        effects::TrashInstruction,
+        observation::{PileObservation, PlayerObservation},
+        player_counters::PlayerCounters,
+        players::TurnPhase,
+    };
+
+    fn observation() -> Observation {
+        Observation {
+            turn: 3,
+            seat: 0,
+            hand: vec![CardNames::COPPER, CardNames::ESTATE, CardNames::SMITHY],
+            deck: vec![CardNames::COPPER; 4],
+            discard: vec![CardNames::ESTATE, CardNames::SILVER],
+            in_play: vec![CardNames::SILVER],
+            counters: PlayerCounters::new_turn(),
+            supply: vec![
+                PileObservation {
+                    name: CardNames::COPPER,
+                    cost: Some(0),
+                    remaining: 46,
+                },
+                PileObservation {
+                    name: CardNames::SILVER,
+                    cost: Some(3),
+                    remaining: 40,
+                },
+                PileObservation {
+                    name: CardNames::SMITHY,
+                    cost: None,
+                    remaining: 0,
+                },
+            ],
+            players: vec![
+                PlayerObservation {
+                    seat: 0,
+                    hand_size: 3,
+                    deck_size: 4,
+                    discard_size: 2,
+                    in_play: vec![CardNames::SILVER],
+                    score: 2,
+                },
+                PlayerObservation {
+                    seat: 1,
+                    hand_size: 5,
+                    deck_size: 0,
+                    discard_size: 7,
+                    in_play: vec![],
+                    score: 9,
+                },
+            ],
+        }
+    }
+
+    fn buy_request() -> ChoiceRequest {
+        ChoiceRequest::new(
+            &[TurnStateStackEntry::Phase(TurnPhase::Buy)],
+            AgentQuestion::BuyCard(vec![CardNames::COPPER, CardNames::SILVER].into()),
+        )
+    }
+
+    fn trash_request() -> ChoiceRequest {
+        ChoiceRequest::new(
+            &[
+                TurnStateStackEntry::Phase(TurnPhase::Action),
+                TurnStateStackEntry::Card(CardNames::CHAPEL),
+            ],
+            AgentQuestion::TrashCardsFromHand(
+                vec![CardNames::COPPER, CardNames::ESTATE, CardNames::ESTATE].into(),
+                TrashInstruction {
+                    min_cards_to_trash: 0,
+                    max_cards_to_trash: 4,
+                },
+            ),
+        )
+    }
+
+    /** The response, and everything that got shown along the way */
+    fn answer(input: &str, request: &ChoiceRequest) -> (ChoiceResponse, String) {
+        let mut output = vec![];
+        let mut human = HumanAgent::new(input.as_bytes(), &mut output);
+        let response = human.request_choice(request, &observation());
+        (response, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn shows_the_game_and_the_options() {
+        let (_, output) = answer("\n", &buy_request());
+        insta::assert_snapshot!(output);
+    }
+
+    #[test]
+    fn options_can_be_picked_by_number_or_name() {
+        assert_eq!(
+            ChoiceResponse::one(CardNames::SILVER),
+            answer("2\n", &buy_request()).0
+        );
+        assert_eq!(
+            ChoiceResponse::one(CardNames::SILVER),
+            answer(" silver \n", &buy_request()).0
+        );
+        assert_eq!(
+            ChoiceResponse(vec![CardNames::ESTATE, CardNames::COPPER].into()),
+            answer("estate, 1\n", &trash_request()).0
+        );
+    }
+
+    #[test]
+    fn a_blank_line_picks_nothing() {
+        assert_eq!(ChoiceResponse::none(), answer("\n", &buy_request()).0);
+    }
+
+    #[test]
+    fn invalid_choices_get_asked_again() {
+        let (response, output) = answer("Gold\n5\nestate,estate,estate\n2,3\n", &trash_request());
+
+        assert_eq!(
+            ChoiceResponse(vec![CardNames::ESTATE, CardNames::ESTATE].into()),
+            response
+        );
+        insta::assert_snapshot!(output);
+    }
+
+    #[test]
+    fn running_out_of_input_gives_the_default_answer() {
+        let request = ChoiceRequest::new(
+            &[TurnStateStackEntry::Attacked],
+            AgentQuestion::DiscardCardsFromHand(
+                vec![CardNames::COPPER, CardNames::ESTATE].into(),
+                1,
+            ),
+        );
+
+        let (response, _) = answer("", &request);
+
+        assert_eq!(request.question().default_response(), response);
+    }
+}
@@ -0,0 +1,704 @@
+use std::{
+    fmt::{Display, Formatter},
+    io::{self, BufRead, BufReader, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    process::{Child, Command, Stdio},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    cards::CardName,
+    observation::Observation,
+    players::{
+        Agent2, ChoiceRequest, ChoiceResponse, InvalidChoice, TurnPhase, TurnStateStackEntry,
+    },
+};
+
+/** What gets sent to a remote agent for each decision, as a single line of JSON.
+ *
+ * The agent answers each one with a `RemoteResponse` on a line of its own.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteRequest {
+    /** Counts up from 1, so that answers can be matched up with questions */
+    pub id: u64,
+    /** The phase of the turn we're in. If we're being attacked, this is the
+     * attacker's phase
+     */
+    pub phase: Option<TurnPhase>,
+    /** Everything that's going on, ending with the question itself */
+    pub request: ChoiceRequest,
+    /** The cards that can be picked, with `min` to `max` of them needing picking */
+    pub options: Vec<CardName>,
+    pub min: usize,
+    pub max: usize,
+    pub observation: Observation,
+}
+
+impl RemoteRequest {
+    pub fn new(id: u64, request: &ChoiceRequest, observation: &Observation) -> Self {
+        let question = request.question();
+        let (min, max) = question.bounds();
+        RemoteRequest {
+            id,
+            phase: request.stack().iter().rev().find_map(|e| match e {
+                TurnStateStackEntry::Phase(phase) => Some(*phase),
+                _ => None,
+            }),
+            request: request.clone(),
+            options: question.options().to_vec(),
+            min,
+            max,
+            observation: observation.clone(),
+        }
+    }
+}
+
+/** A remote agent's answer to the request with the same id */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteResponse {
+    pub id: u64,
+    /** The names of the cards picked, which can be empty */
+    pub choice: Vec<String>,
+}
+
+/** Everything that can go wrong with asking a remote agent for a decision */
+#[derive(Debug)]
+pub enum RemoteError {
+    Io(io::Error),
+    /** The agent closed the connection (or exited) */
+    HungUp,
+    TimedOut(Duration),
+    /** The agent sent something that isn't a `RemoteResponse` */
+    BadResponse(String),
+    /** The agent picked a card that wasn't one of the options */
+    NotAnOption(String),
+    /** The agent picked the wrong number of cards */
+    Illegal(InvalidChoice),
+}
+
+impl Display for RemoteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoteError::Io(e) => write!(f, "couldn't talk to the agent: {}", e),
+            RemoteError::HungUp => write!(f, "the agent hung up"),
+            RemoteError::TimedOut(timeout) => {
+                write!(f, "the agent didn't answer within {:?}", timeout)
+            }
+            RemoteError::BadResponse(e) => write!(f, "couldn't understand the agent: {}", e),
+            RemoteError::NotAnOption(card) => write!(f, "{:?} wasn't one of the options", card),
+            RemoteError::Illegal(e) => write!(f, "that's not a valid answer: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for RemoteError {}
+
+/** Something that went wrong with a remote agent, and what happens next */
+#[derive(Debug)]
+pub struct RemoteProblem<'a> {
+    pub agent: &'a str,
+    pub error: &'a RemoteError,
+    /** Whether the agent has been given up on for the rest of the game */
+    pub disconnected: bool,
+}
+
+impl Display for RemoteProblem<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.disconnected {
+            write!(
+                f,
+                "{}: {}, so it won't be asked anything else",
+                self.agent, self.error
+            )
+        } else {
+            write!(
+                f,
+                "{}: {}, so it gets the default answer",
+                self.agent, self.error
+            )
+        }
+    }
+}
+
+/** What to do when a remote agent doesn't give us a valid answer in time */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IllegalMovePolicy {
+    /** Use the question's default answer, but keep asking the agent */
+    #[default]
+    PlayDefault,
+    /** Use default answers for the rest of the game, without asking the agent again */
+    Disconnect,
+    /** Stop the game with a panic, which is handy when testing a new agent */
+    Abort,
+}
+
+#[derive(Debug)]
+enum Connection {
+    Process(Child),
+    Tcp(TcpStream),
+    Streams,
+}
+
+/** An agent that lives somewhere else, like a bot written in another language.
+ * It gets sent a `RemoteRequest` for each decision, and sends back a
+ * `RemoteResponse`, one JSON object per line in each direction.
+ *
+ * The agent can be a child process talking over its stdin and stdout, or
+ * something listening on a TCP socket. Either way it's only used for one game,
+ * and a child process gets killed once the game's over.
+ */
+pub struct RemoteAgent {
+    name: String,
+    output: Box<dyn Write>,
+    /** Lines get read on another thread, so that we can give up waiting for them */
+    input: Receiver<io::Result<String>>,
+    connection: Connection,
+    timeout: Duration,
+    policy: IllegalMovePolicy,
+    last_id: u64,
+    disconnected: bool,
+    report: Box<dyn FnMut(&RemoteProblem)>,
+}
+
+impl std::fmt::Debug for RemoteAgent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteAgent")
+            .field("name", &self.name)
+            .field("connection", &self.connection)
+            .finish()
+    }
+}
+
+impl RemoteAgent {
+    pub fn new(
+        name: impl Into<String>,
+        input: impl BufRead + Send + 'static,
+        output: impl Write + 'static,
+    ) -> Self {
+        RemoteAgent::with_connection(name, input, output, Connection::Streams)
+    }
+
+    /** Starts `command`, talking to it over its stdin and stdout */
+    pub fn spawn(name: impl Into<String>, command: &mut Command) -> io::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("BUG: stdin should be piped");
+        let stdout = child.stdout.take().expect("BUG: stdout should be piped");
+        Ok(RemoteAgent::with_connection(
+            name,
+            BufReader::new(stdout),
+            stdin,
+            Connection::Process(child),
+        ))
+    }
+
+    /** Like `spawn`, but for a command line like "python3 'my bot.py' --fast".
+     * Arguments are split on whitespace, except inside single or double quotes.
+     * Outside single quotes, a backslash before a quote, space or backslash makes
+     * that an ordinary character (and any other backslash is left alone, so
+     * Windows paths work as they are).
+     */
+    pub fn spawn_command_line(name: impl Into<String>, command_line: &str) -> io::Result<Self> {
+        let words = split_command_line(command_line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let (program, args) = words
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "there's no command"))?;
+        RemoteAgent::spawn(name, Command::new(program).args(args))
+    }
+
+    pub fn connect(name: impl Into<String>, address: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        // every message is a single small line that's waited on
+        stream.set_nodelay(true)?;
+        let input = BufReader::new(stream.try_clone()?);
+        let output = stream.try_clone()?;
+        Ok(RemoteAgent::with_connection(
+            name,
+            input,
+            output,
+            Connection::Tcp(stream),
+        ))
+    }
+
+    fn with_connection(
+        name: impl Into<String>,
+        input: impl BufRead + Send + 'static,
+        output: impl Write + 'static,
+        connection: Connection,
+    ) -> Self {
+        let (lines, receiver) = channel();
+        std::thread::spawn(move || {
+            for line in input.lines() {
+                let failed = line.is_err();
+                if lines.send(line).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        RemoteAgent {
+            name: name.into(),
+            output: Box::new(output),
+            input: receiver,
+            connection,
+            timeout: Duration::from_secs(5),
+            policy: IllegalMovePolicy::default(),
+            last_id: 0,
+            disconnected: false,
+            report: Box::new(|_| {}),
+        }
+    }
+
+    /** How long the agent gets to answer each request (5 seconds by default) */
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn set_illegal_move_policy(&mut self, policy: IllegalMovePolicy) {
+        self.policy = policy;
+    }
+
+    /** Gets told whenever the agent doesn't give a valid answer in time
+     * (which by default nobody hears about)
+     */
+    pub fn set_problem_reporter(&mut self, report: impl FnMut(&RemoteProblem) + 'static) {
+        self.report = Box::new(report);
+    }
+
+    fn ask(
+        &mut self,
+        request: &ChoiceRequest,
+        observation: &Observation,
+    ) -> Result<ChoiceResponse, RemoteError> {
+        self.last_id += 1;
+        let id = self.last_id;
+        let message = serde_json::to_string(&RemoteRequest::new(id, request, observation))
+            .expect("BUG: requests should always be serializable");
+        send_line(&mut self.output, &message).map_err(RemoteError::Io)?;
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let line = match self
+                .input
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(line) => line.map_err(RemoteError::Io)?,
+                Err(RecvTimeoutError::Timeout) => return Err(RemoteError::TimedOut(self.timeout)),
+                Err(RecvTimeoutError::Disconnected) => return Err(RemoteError::HungUp),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response: RemoteResponse =
+                serde_json::from_str(&line).map_err(|e| RemoteError::BadResponse(e.to_string()))?;
+            // a late answer to a question we've already given up on
+            if response.id < id {
+                continue;
+            }
+            if response.id > id {
+                return Err(RemoteError::BadResponse(format!(
+                    "expected an answer to request {} but got one for {}",
+                    id, response.id
+                )));
+            }
+            return choose(request, &response.choice);
+        }
+    }
+
+    fn give_up(&mut self, error: RemoteError) {
+        let hung_up = matches!(error, RemoteError::Io(_) | RemoteError::HungUp);
+        match self.policy {
+            IllegalMovePolicy::Abort => panic!("{}: {}", self.name, error),
+            IllegalMovePolicy::Disconnect => self.disconnected = true,
+            IllegalMovePolicy::PlayDefault => self.disconnected = hung_up,
+        }
+        (self.report)(&RemoteProblem {
+            agent: &self.name,
+            error: &error,
+            disconnected: self.disconnected,
+        });
+    }
+}
+
+impl Agent2 for RemoteAgent {
+    fn request_choice(
+        &mut self,
+        request: &ChoiceRequest,
+        observation: &Observation,
+    ) -> ChoiceResponse {
+        if !self.disconnected {
+            match self.ask(request, observation) {
+                Ok(response) => return response,
+                Err(e) => self.give_up(e),
+            }
+        }
+        request.question().default_response()
+    }
+}
+
+impl Drop for RemoteAgent {
+    fn drop(&mut self) {
+        // the thread reading from the agent stops once there's nothing left to read
+        match &mut self.connection {
+            Connection::Process(child) => {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+            Connection::Tcp(stream) => {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+            Connection::Streams => {}
+        }
+    }
+}
+
+/** Turns the names the agent sent back into the options they refer to */
+fn choose(request: &ChoiceRequest, names: &[String]) -> Result<ChoiceResponse, RemoteError> {
+    let options = request.question().options();
+    let chosen = names
+        .iter()
+        .map(|name| {
+            options
+                .iter()
+                .find(|o| o.as_str() == name)
+                .copied()
+                .ok_or_else(|| RemoteError::NotAnOption(name.clone()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let response = ChoiceResponse(chosen.into());
+    request.validate(&response).map_err(RemoteError::Illegal)?;
+    Ok(response)
+}
+
+/** The simplest possible remote agent, which always picks as few of the
+ * options as it can. This is handy for trying out the protocol, and for
+ * testing remote agents without needing another language installed.
+ */
+pub fn echo_bot(input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: RemoteRequest = serde_json::from_str(&line).map_err(io::Error::other)?;
+        let response = RemoteResponse {
+            id: request.id,
+            choice: request
+                .options
+                .iter()
+                .take(request.min)
+                .map(|c| c.as_str().to_owned())
+                .collect(),
+        };
+        let message = serde_json::to_string(&response).map_err(io::Error::other)?;
+        send_line(&mut output, &message)?;
+    }
+    Ok(())
+}
+
+/** See `RemoteAgent::spawn_command_line` */
+fn split_command_line(line: &str) -> Result<Vec<String>, String> {
+    let special = |c: &char| c.is_whitespace() || ['\'', '"', '\\'].contains(c);
+    let mut words = vec![];
+    // (quotes can make an empty word)
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            // everything's taken literally inside single quotes
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), c) => word.get_or_insert_with(String::new).push(c),
+            (_, '\\') if chars.peek().is_some_and(special) => {
+                let escaped = chars.next().expect("BUG: we just peeked at it");
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            (Some('"'), '"') => quote = None,
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (_, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(q) = quote {
+        return Err(format!("there's no closing {} in {:?}", q, line));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/** Writing the line all at once means it goes in a single packet over TCP */
+fn send_line(output: &mut impl Write, message: &str) -> io::Result<()> {
+    output.write_all(format!("{}\n", message).as_bytes())?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        net::{SocketAddr, TcpListener},
+        rc::Rc,
+    };
+
+    use super::*;
+    use crate::{
+        game::{
+            cards::CardNames, effects::TrashInstruction, player_counters::PlayerCounters,
+            players::AgentQuestion, Game,
+        },
+        simulation::Simulation,
+    };
+
+    /** Answers the way the echo bot does, but without going over the network */
+    #[derive(Debug)]
+    struct DefaultAnswers;
+    impl Agent2 for DefaultAnswers {
+        fn request_choice(&mut self, request: &ChoiceRequest, _: &Observation) -> ChoiceResponse {
+            request.question().default_response()
+        }
+    }
+
+    /** Runs `bot` on the other end of a local socket, for a single connection */
+    fn serve(bot: impl FnOnce(BufReader<TcpStream>, TcpStream) + Send + 'static) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            bot(BufReader::new(stream.try_clone().unwrap()), stream);
+        });
+        address
+    }
+
+    fn observation() -> Observation {
+        Observation {
+            turn: 1,
+            seat: 0,
+            hand: vec![CardNames::COPPER, CardNames::ESTATE],
+            deck: vec![],
+            discard: vec![],
+            in_play: vec![],
+            counters: PlayerCounters::new_turn(),
+            supply: vec![],
+            players: vec![],
+        }
+    }
+
+    fn buy_request() -> ChoiceRequest {
+        ChoiceRequest::new(
+            &[TurnStateStackEntry::Phase(TurnPhase::Buy)],
+            AgentQuestion::BuyCard(vec![CardNames::COPPER, CardNames::SILVER].into()),
+        )
+    }
+
+    fn trash_request() -> ChoiceRequest {
+        ChoiceRequest::new(
+            &[
+                TurnStateStackEntry::Phase(TurnPhase::Action),
+                TurnStateStackEntry::Card(CardNames::CHAPEL),
+            ],
+            AgentQuestion::TrashCardsFromHand(
+                vec![CardNames::COPPER, CardNames::ESTATE].into(),
+                TrashInstruction {
+                    min_cards_to_trash: 1,
+                    max_cards_to_trash: 1,
+                },
+            ),
+        )
+    }
+
+    /** Reads a request, and sends back whatever the script says for it */
+    fn answer_with(
+        mut input: impl BufRead,
+        mut output: impl Write,
+        respond: impl Fn(RemoteRequest) -> String,
+    ) {
+        let mut line = String::new();
+        while input.read_line(&mut line).unwrap() > 0 {
+            let request = serde_json::from_str(&line).unwrap();
+            send_line(&mut output, &respond(request)).unwrap();
+            line.clear();
+        }
+    }
+
+    fn setup_supply(game: &mut Game) {
+        game.populate_basic_kingdom();
+    }
+
+    #[test]
+    fn the_echo_bot_plays_a_whole_game_over_tcp() {
+        let mut local = Simulation::new(1, 7);
+        local.add_player("P1", || Box::new(DefaultAnswers));
+        local.set_supply(setup_supply);
+        let expected = local.run().games.remove(0);
+
+        let address = serve(|input, output| echo_bot(input, output).unwrap());
+        let mut remote = Simulation::new(1, 7);
+        remote.add_player("P1", move || {
+            Box::new(RemoteAgent::connect("echo", address).unwrap())
+        });
+        remote.set_supply(setup_supply);
+
+        assert_eq!(expected, remote.run().games.remove(0));
+    }
+
+    #[test]
+    fn requests_describe_the_decision() {
+        let request = RemoteRequest::new(3, &trash_request(), &observation());
+
+        insta::assert_snapshot!(serde_json::to_string_pretty(&request).unwrap());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn agents_can_be_child_processes() {
+        // a bot that wants to buy silver, whatever it gets asked
+        let script = r#"while read -r line; do
+            id=${line#*\"id\":}
+            echo "{\"id\":${id%%,*},\"choice\":[\"Silver\"]}"
+        done"#;
+        let mut agent =
+            RemoteAgent::spawn("silver", Command::new("sh").args(["-c", script])).unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(
+                ChoiceResponse::one(CardNames::SILVER),
+                agent.request_choice(&buy_request(), &observation())
+            );
+        }
+    }
+
+    #[test]
+    fn illegal_moves_get_the_default_answer() {
+        let address = serve(|input, output| {
+            answer_with(input, output, |r| {
+                format!(r#"{{"id":{},"choice":["Province"]}}"#, r.id)
+            })
+        });
+        let mut agent = RemoteAgent::connect("greedy", address).unwrap();
+
+        assert_eq!(
+            ChoiceResponse::one(CardNames::COPPER),
+            agent.request_choice(&trash_request(), &observation())
+        );
+        // it still gets asked next time
+        assert_eq!(
+            ChoiceResponse::one(CardNames::COPPER),
+            agent.request_choice(&trash_request(), &observation())
+        );
+        assert_eq!(2, agent.last_id);
+    }
+
+    #[test]
+    fn disconnected_agents_dont_get_asked_again() {
+        let address = serve(|input, output| {
+            answer_with(input, output, |r| {
+                format!(r#"{{"id":{},"choice":[]}}"#, r.id)
+            })
+        });
+        let mut agent = RemoteAgent::connect("lazy", address).unwrap();
+        agent.set_illegal_move_policy(IllegalMovePolicy::Disconnect);
+
+        agent.request_choice(&trash_request(), &observation());
+        let response = agent.request_choice(&buy_request(), &observation());
+
+        assert_eq!(ChoiceResponse::none(), response);
+        assert_eq!(1, agent.last_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a valid answer")]
+    fn illegal_moves_can_stop_the_game() {
+        let address = serve(|input, output| {
+            answer_with(input, output, |r| {
+                format!(r#"{{"id":{},"choice":[]}}"#, r.id)
+            })
+        });
+        let mut agent = RemoteAgent::connect("lazy", address).unwrap();
+        agent.set_illegal_move_policy(IllegalMovePolicy::Abort);
+
+        agent.request_choice(&trash_request(), &observation());
+    }
+
+    #[test]
+    fn late_answers_are_ignored() {
+        // the first request only gets answered once we've given up on it and
+        // sent the second one, so the late answer arrives just before the right one
+        let address = serve(|mut input, mut output| {
+            let mut line = String::new();
+            input.read_line(&mut line).unwrap();
+            input.read_line(&mut line).unwrap();
+            send_line(&mut output, r#"{"id":1,"choice":["Copper"]}"#).unwrap();
+            send_line(&mut output, r#"{"id":2,"choice":["Silver"]}"#).unwrap();
+        });
+        let mut agent = RemoteAgent::connect("slow", address).unwrap();
+        agent.set_timeout(Duration::from_millis(50));
+
+        // the first answer comes too late, so it gets the default
+        assert_eq!(
+            ChoiceResponse::none(),
+            agent.request_choice(&buy_request(), &observation())
+        );
+        agent.set_timeout(Duration::from_secs(5));
+        assert_eq!(
+            ChoiceResponse::one(CardNames::SILVER),
+            agent.request_choice(&buy_request(), &observation())
+        );
+    }
+
+    #[test]
+    fn agents_that_hang_up_get_default_answers() {
+        let address = serve(|_, _| {});
+        let mut agent = RemoteAgent::connect("gone", address).unwrap();
+
+        assert_eq!(
+            ChoiceResponse::one(CardNames::COPPER),
+            agent.request_choice(&trash_request(), &observation())
+        );
+        assert!(agent.disconnected);
+    }
+
+    #[test]
+    fn problems_get_reported() {
+        let mut agent = RemoteAgent::new("gone", io::empty(), io::sink());
+        let reports = Rc::new(RefCell::new(vec![]));
+        let reported = reports.clone();
+        agent.set_problem_reporter(move |problem| reported.borrow_mut().push(problem.to_string()));
+
+        agent.request_choice(&trash_request(), &observation());
+
+        assert_eq!(
+            vec!["gone: the agent hung up, so it won't be asked anything else"],
+            *reports.borrow()
+        );
+    }
+
+    #[test]
+    fn command_lines_can_have_quoted_arguments() {
+        let split = |line| split_command_line(line).unwrap();
+
+        assert_eq!(vec!["bot", "--fast"], split("  bot   --fast "));
+        assert_eq!(
+            vec!["python3", "my bot.py", "it's", ""],
+            split(r#"python3 'my bot.py' "it's" """#)
+        );
+        assert_eq!(vec!["my bot", r#"a"b"#], split(r#"my\ bot a\"b"#));
+        assert_eq!(vec![r"C:\bots\bot.exe"], split(r"C:\bots\bot.exe"));
+        assert_eq!(vec![r"a\ b"], split(r"'a\ b'"));
+        assert!(split_command_line("bot 'unfinished").is_err());
+    }
+
+    #[test]
+    fn commands_that_cant_be_started_are_an_error() {
+        assert!(RemoteAgent::spawn_command_line("nobody", "").is_err());
+        assert!(RemoteAgent::spawn_command_line("nobody", "./there-is-no-such-bot").is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt::{Display, Formatter};
+
+use derive_more::Constructor;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use super::cards::{Card, CardName};
+
+#[derive(Debug, Constructor)]
+pub struct PlayerResult<'a> {
+    pub name: &'a str,
+    cards: Vec<Card>,
+    pub score: i16,
+    pub turns_taken: u8,
+}
+
+impl PlayerResult<'_> {
+    /** Official tiebreaker: if scores are level then whoever took fewer turns wins */
+    fn beats(&self, other: &PlayerResult) -> bool {
+        self.score > other.score
+            || (self.score == other.score && self.turns_taken < other.turns_taken)
+    }
+}
+
+impl Display for PlayerResult<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}: {} points in {} turns",
+            self.name, self.score, self.turns_taken
+        )?;
+        for (name, cards) in &self.cards.iter().group_by(|c| c.name) {
+            writeln!(f, "  {:?} x{}", name, cards.count())?
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameEndReason {
+    /** The Province or Colony pile ran out */
+    PileEmptied(CardName),
+    /** Three supply piles ran out (or four, in games with 5+ players) */
+    PilesEmptied(Box<[CardName]>),
+    /** Nobody managed to finish the game within the turn limit */
+    TurnLimitReached(u8),
+}
+
+impl Display for GameEndReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameEndReason::PileEmptied(pile) => write!(f, "the {:?} pile ran out", pile),
+            GameEndReason::PilesEmptied(piles) => {
+                write!(f, "{} supply piles ran out {:?}", piles.len(), piles)
+            }
+            GameEndReason::TurnLimitReached(turns) => {
+                write!(f, "the turn limit ({}) was reached", turns)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlayerOutcome {
+    Win,
+    /** Tied on both points and turns with at least one other player */
+    SharedWin,
+    Loss,
+}
+
+pub struct PlayerResults<'a> {
+    /** In seating order */
+    pub players: Vec<PlayerResult<'a>>,
+    pub end_reason: GameEndReason,
+}
+
+impl<'a> PlayerResults<'a> {
+    pub fn new(players: Vec<PlayerResult<'a>>, end_reason: GameEndReason) -> Self {
+        PlayerResults {
+            players,
+            end_reason,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PlayerResult<'a>> {
+        self.players.iter().find(|p| p.name == name)
+    }
+
+    /** 1 for the winner(s), and tied players share a placement
+     * (so two players tied for first are followed by 3rd place)
+     */
+    pub fn placement(&self, player: &PlayerResult) -> usize {
+        1 + self.players.iter().filter(|p| p.beats(player)).count()
+    }
+
+    pub fn winners(&self) -> Vec<&'a str> {
+        self.players
+            .iter()
+            .filter(|p| self.placement(p) == 1)
+            .map(|p| p.name)
+            .collect()
+    }
+
+    pub fn outcome_for(&self, name: &str) -> Option<PlayerOutcome> {
+        let player = self.get(name)?;
+        if self.placement(player) > 1 {
+            Some(PlayerOutcome::Loss)
+        } else if self.winners().len() > 1 {
+            Some(PlayerOutcome::SharedWin)
+        } else {
+            Some(PlayerOutcome::Win)
+        }
+    }
+}
+
+impl Display for PlayerResults<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Game ended because {}", self.end_reason)?;
+        writeln!(f)?;
+        for result in &self.players {
+            write!(f, "#{} ", self.placement(result))?;
+            writeln!(f, "{}", result)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(players: &[(&'static str, i16, u8)]) -> PlayerResults<'static> {
+        PlayerResults::new(
+            players
+                .iter()
+                .map(|&(name, score, turns)| PlayerResult::new(name, vec![], score, turns))
+                .collect(),
+            GameEndReason::TurnLimitReached(100),
+        )
+    }
+
+    #[test]
+    fn highest_score_wins() {
+        let results = results(&[("P1", 10, 20), ("P2", 12, 20)]);
+
+        assert_eq!(vec!["P2"], results.winners());
+        assert_eq!(Some(PlayerOutcome::Loss), results.outcome_for("P1"));
+        assert_eq!(Some(PlayerOutcome::Win), results.outcome_for("P2"));
+    }
+
+    #[test]
+    fn tied_scores_are_won_by_whoever_took_fewer_turns() {
+        let results = results(&[("P1", 12, 20), ("P2", 12, 19)]);
+
+        assert_eq!(vec!["P2"], results.winners());
+        assert_eq!(Some(PlayerOutcome::Loss), results.outcome_for("P1"));
+    }
+
+    #[test]
+    fn tied_scores_and_turns_share_the_win() {
+        let results = results(&[("P1", 12, 20), ("P2", 12, 20), ("P3", 3, 20)]);
+
+        assert_eq!(vec!["P1", "P2"], results.winners());
+        assert_eq!(Some(PlayerOutcome::SharedWin), results.outcome_for("P1"));
+        assert_eq!(Some(PlayerOutcome::SharedWin), results.outcome_for("P2"));
+        assert_eq!(3, results.placement(results.get("P3").unwrap()));
+    }
+
+    #[test]
+    fn unknown_players_have_no_outcome() {
+        let results = results(&[("P1", 12, 20)]);
+
+        assert_eq!(None, results.outcome_for("P4"));
+    }
+}
@@ -1,6 +1,11 @@
-use std::cell::RefCell;
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    fmt::{Display, Formatter},
+};
 
 use derive_more::Constructor;
+use itertools::Itertools;
 use rand::{self, Rng, SeedableRng};
 // We use Pcg64Mcg over StdRng because it's faster and we don't need cryptographic security.
 // Also referencing Pcg64Mcg directly instead of SmallRng since apparently SmallRng can
@@ -9,26 +14,45 @@ use rand::{self, Rng, SeedableRng};
 // (Although tbh if we're going to serialize games, we might just want to serialize the set
 // of cards that got drawn rather than the seed used to pick them)
 use rand_pcg::Pcg64Mcg as PRng;
+use serde::{Deserialize, Serialize};
+
+use super::cards::{Card, CardName};
 
 pub trait Shuffler<T>: std::fmt::Debug {
     /**
      * Consumes values from input and returns a shuffled Vec.
      */
     fn shuffle(&self, input: &mut Vec<T>) -> Vec<T>;
+
+    /** For saving a game part way through. Shufflers without any state
+     * (or that can't be saved) don't need to implement this.
+     */
+    fn state(&self) -> Option<ShufflerState> {
+        None
+    }
 }
 
+/** Where a `RandomShuffler` is up to, so it can carry on from the same place later */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShufflerState(PRng);
+
 #[derive(Debug)]
 pub struct RandomShuffler {
     rng: RefCell<PRng>,
 }
 impl RandomShuffler {
-    #[cfg(test)]
     pub fn new(seed: u64) -> Self {
         RandomShuffler {
             rng: PRng::seed_from_u64(seed).into(),
         }
     }
 
+    pub fn from_state(state: ShufflerState) -> Self {
+        RandomShuffler {
+            rng: state.0.into(),
+        }
+    }
+
     pub fn unseeded() -> Self {
         RandomShuffler {
             rng: PRng::from_entropy().into(),
@@ -45,28 +69,204 @@ impl<T> Shuffler<T> for RandomShuffler {
         }
         result
     }
+
+    fn state(&self) -> Option<ShufflerState> {
+        Some(ShufflerState(self.rng.borrow().clone()))
+    }
 }
 
 #[derive(Debug, Constructor)]
 pub struct NoShuffle;
 impl<T> Shuffler<T> for NoShuffle {
     fn shuffle(&self, input: &mut Vec<T>) -> Vec<T> {
-        input.drain(..).collect()
+        std::mem::take(input)
     }
 }
 
+/** Something a `PredestinedShuffler` was asked to do that its script didn't allow for */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PredestinedShuffleError {
+    /** Every scripted shuffle has already been used */
+    Exhausted { owner: String, shuffles: usize },
+    /** The script was for different cards than the ones being shuffled */
+    WrongCards {
+        owner: String,
+        /** Counting from 1 */
+        shuffle: usize,
+        scripted: Vec<CardName>,
+        actual: Vec<CardName>,
+    },
+}
+
+impl Display for PredestinedShuffleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PredestinedShuffleError::Exhausted { owner, shuffles } => write!(
+                f,
+                "{} shuffled again, but only had {} shuffles scripted",
+                owner, shuffles
+            ),
+            PredestinedShuffleError::WrongCards {
+                owner,
+                shuffle,
+                scripted,
+                actual,
+            } => write!(
+                f,
+                "shuffle {} for {} was of {:?} but the script says {:?}",
+                shuffle,
+                owner,
+                actual.iter().sorted().collect_vec(),
+                scripted.iter().sorted().collect_vec()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PredestinedShuffleError {}
+
 /**
- * Throws away input and returns cards from test data instead
+ * Puts cards in a scripted order instead of shuffling them, eg so that a test can
+ * say "this player's second shuffle produces Gold, Gold, Smithy, ...".
+ *
+ * Each player should get their own, since the script is a queue of that player's
+ * shuffles. Each shuffle lists the cards in the order they'll be drawn, and has to
+ * be of exactly the cards that end up being shuffled.
  */
 #[derive(Debug)]
-pub struct PredestinedShuffler<T> {
-    cards: RefCell<Vec<T>>,
+pub struct PredestinedShuffler {
+    owner: String,
+    shuffles: RefCell<VecDeque<Vec<CardName>>>,
+    shuffled: Cell<usize>,
 }
-impl<T> Shuffler<T> for PredestinedShuffler<T>
-where
-    T: std::fmt::Debug,
-{
-    fn shuffle(&self, input: &mut Vec<T>) -> Vec<T> {
-        self.cards.borrow_mut().drain(0..input.len()).collect()
+impl PredestinedShuffler {
+    pub fn new(
+        owner: impl Into<String>,
+        shuffles: impl IntoIterator<Item = Vec<CardName>>,
+    ) -> Self {
+        PredestinedShuffler {
+            owner: owner.into(),
+            shuffles: shuffles.into_iter().collect::<VecDeque<_>>().into(),
+            shuffled: 0.into(),
+        }
+    }
+
+    /** How many scripted shuffles haven't happened yet */
+    pub fn remaining(&self) -> usize {
+        self.shuffles.borrow().len()
+    }
+
+    /** Like `shuffle`, but returns an error rather than panicking if the script
+     * doesn't fit. The input is left alone if there's an error.
+     */
+    pub fn try_shuffle(&self, input: &mut Vec<Card>) -> Result<Vec<Card>, PredestinedShuffleError> {
+        let scripted = self.shuffles.borrow_mut().pop_front().ok_or_else(|| {
+            PredestinedShuffleError::Exhausted {
+                owner: self.owner.clone(),
+                shuffles: self.shuffled.get(),
+            }
+        })?;
+        self.shuffled.set(self.shuffled.get() + 1);
+
+        let sorted_input = input.iter().map(|c| c.name).sorted().collect_vec();
+        let sorted_script = scripted.iter().cloned().sorted().collect_vec();
+        if sorted_input != sorted_script {
+            return Err(PredestinedShuffleError::WrongCards {
+                owner: self.owner.clone(),
+                shuffle: self.shuffled.get(),
+                scripted,
+                actual: input.iter().map(|c| c.name).collect(),
+            });
+        }
+
+        // the deck gets drawn from the end, so the first card drawn goes last
+        let mut result = Vec::with_capacity(input.len());
+        for name in scripted.iter().rev() {
+            let index = input
+                .iter()
+                .position(|c| c.name == *name)
+                .expect("BUG: we already checked the script has the same cards");
+            result.push(input.swap_remove(index));
+        }
+        Ok(result)
+    }
+}
+impl Shuffler<Card> for PredestinedShuffler {
+    fn shuffle(&self, input: &mut Vec<Card>) -> Vec<Card> {
+        self.try_shuffle(input).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::cards::{CardNames, Cards};
+
+    fn names(cards: &[Card]) -> Vec<CardName> {
+        cards.iter().map(|c| c.name).collect()
+    }
+
+    #[test]
+    fn random_shufflers_carry_on_from_a_saved_state() {
+        let shuffler = RandomShuffler::new(1234);
+        shuffler.shuffle(&mut (0..10).collect_vec());
+        let restored = RandomShuffler::from_state(Shuffler::<u8>::state(&shuffler).unwrap());
+
+        assert_eq!(
+            shuffler.shuffle(&mut (0..10).collect_vec()),
+            restored.shuffle(&mut (0..10).collect_vec())
+        );
+    }
+
+    #[test]
+    fn predestined_shuffles_come_out_in_the_scripted_order() {
+        let shuffler = PredestinedShuffler::new(
+            "P1",
+            [
+                vec![CardNames::COPPER, CardNames::ESTATE],
+                vec![CardNames::GOLD, CardNames::GOLD, CardNames::SMITHY],
+            ],
+        );
+
+        let first = shuffler.shuffle(&mut vec![Cards::estate(), Cards::copper()]);
+        let mut input = vec![Cards::smithy(), Cards::gold(), Cards::gold()];
+        let second = shuffler.shuffle(&mut input);
+
+        // the deck is drawn from the end
+        assert_eq!(vec![CardNames::ESTATE, CardNames::COPPER], names(&first));
+        assert_eq!(
+            vec![CardNames::SMITHY, CardNames::GOLD, CardNames::GOLD],
+            names(&second)
+        );
+        assert!(input.is_empty());
+        assert_eq!(0, shuffler.remaining());
+    }
+
+    #[test]
+    fn predestined_shuffles_have_to_be_of_the_same_cards() {
+        let shuffler = PredestinedShuffler::new("P1", [vec![CardNames::GOLD, CardNames::COPPER]]);
+        let mut input = vec![Cards::copper(), Cards::copper()];
+
+        let result = shuffler.try_shuffle(&mut input);
+
+        assert_eq!(
+            Err(PredestinedShuffleError::WrongCards {
+                owner: "P1".to_owned(),
+                shuffle: 1,
+                scripted: vec![CardNames::GOLD, CardNames::COPPER],
+                actual: vec![CardNames::COPPER, CardNames::COPPER],
+            }),
+            result.map(|cards| names(&cards))
+        );
+        assert_eq!(2, input.len());
+    }
+
+    #[test]
+    #[should_panic(expected = "P2 shuffled again, but only had 1 shuffles scripted")]
+    fn running_out_of_predestined_shuffles_says_so() {
+        let shuffler = PredestinedShuffler::new("P2", [vec![CardNames::COPPER]]);
+
+        shuffler.shuffle(&mut vec![Cards::copper()]);
+        shuffler.shuffle(&mut vec![Cards::copper()]);
     }
 }
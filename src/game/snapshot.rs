@@ -0,0 +1,127 @@
+use std::{
+    fmt::{Display, Formatter},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    card_registry::{CardRegistry, CardRegistryError},
+    cards::{Card, CardName},
+    effects::CardEffect,
+    shuffler::ShufflerState,
+};
+
+/** Everything about a game in between two turns, so that it can be saved
+ * to a file and carried on with later.
+ *
+ * Cards are stored by name, so restoring a game needs a registry that knows
+ * about every card in it. Agents aren't part of the snapshot, so agents that
+ * remember things between turns will have forgotten them.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameSnapshot {
+    pub turn_counter: u8,
+    pub max_turns: u8,
+    /** Whose turn is next. 0 means a new round is about to start */
+    #[serde(default)]
+    pub next_seat: usize,
+    /** Each pile's original name, and its cards with the top card last */
+    pub supply: Vec<(CardName, Vec<CardName>)>,
+    pub trash: Vec<CardName>,
+    /** In seating order */
+    pub players: Vec<PlayerSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerSnapshot {
+    pub name: String,
+    pub turns_taken: u8,
+    pub zones: ZonesSnapshot,
+    /** None if the player's shuffler couldn't be saved (eg a `NoShuffle` in tests).
+     * Players who shared a shuffler will all have the same state.
+     */
+    pub shuffler: Option<ShufflerState>,
+}
+
+/** Where all of a player's cards are */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ZonesSnapshot {
+    /** The next card to be drawn is last */
+    pub deck: Vec<CardName>,
+    pub hand: Vec<CardName>,
+    pub in_play: Vec<CardName>,
+    pub set_aside: Vec<CardName>,
+    pub next_turn_effects: Vec<(CardName, CardEffect)>,
+    pub discard: Vec<CardName>,
+    /** Only ever has anything in it partway through a turn (see `GameHistory`) */
+    #[serde(default)]
+    pub revealed: Vec<CardName>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /** The snapshot has a card that the registry doesn't know about */
+    Registry(CardRegistryError),
+    /** Every player in the snapshot needs an agent and a shuffler */
+    WrongNumberOfPlayers {
+        expected: usize,
+        given: usize,
+    },
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "couldn't read or write the snapshot: {}", e),
+            SnapshotError::Json(e) => write!(f, "couldn't parse the snapshot: {}", e),
+            SnapshotError::Registry(e) => e.fmt(f),
+            SnapshotError::WrongNumberOfPlayers { expected, given } => write!(
+                f,
+                "the snapshot has {} players, but {} were given",
+                expected, given
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<CardRegistryError> for SnapshotError {
+    fn from(e: CardRegistryError) -> Self {
+        SnapshotError::Registry(e)
+    }
+}
+
+impl GameSnapshot {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let json = serde_json::to_string(self).map_err(SnapshotError::Json)?;
+        std::fs::write(path, json).map_err(SnapshotError::Io)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let json = std::fs::read_to_string(path).map_err(SnapshotError::Io)?;
+        serde_json::from_str(&json).map_err(SnapshotError::Json)
+    }
+}
+
+pub(super) fn names(cards: &[Card]) -> Vec<CardName> {
+    cards.iter().map(|c| c.name).collect()
+}
+
+/** Turns names back into cards */
+pub(super) fn print_cards(
+    registry: &CardRegistry,
+    names: &[CardName],
+) -> Result<Vec<Card>, CardRegistryError> {
+    names
+        .iter()
+        .map(|&name| {
+            registry
+                .make(name)
+                .ok_or_else(|| CardRegistryError::UnknownCard(name.as_str().to_owned()))
+        })
+        .collect()
+}
@@ -1,6 +1,8 @@
 use super::{
     card_pile::CardPile,
     cards::{Card, CardName},
+    history::{Location, Place},
+    observation::PileObservation,
 };
 
 #[derive(Debug)]
@@ -36,28 +38,91 @@ impl Supply {
             .map(|s| s.name)
     }
 
+    /** How many cards are left in the pile that started with this card on top */
+    #[cfg(test)]
+    pub fn pile_size(&self, pile: CardName) -> Option<usize> {
+        self.supply_piles
+            .iter()
+            .find(|s| s.name == pile)
+            .map(|s| s.pile.len())
+    }
+
+    /** Each pile's original name and how many cards it has left */
+    pub fn piles(&self) -> impl Iterator<Item = (CardName, usize)> + '_ {
+        self.supply_piles.iter().map(|s| (s.name, s.pile.len()))
+    }
+
+    /** What everyone can see of each pile */
+    pub fn observe(&self) -> Vec<PileObservation> {
+        self.supply_piles
+            .iter()
+            .map(|s| {
+                let top = s.pile.peek();
+                PileObservation {
+                    name: top.map_or(s.name, |c| c.name),
+                    cost: top.map(|c| c.coins_cost),
+                    remaining: s.pile.len(),
+                }
+            })
+            .collect()
+    }
+
     pub fn buyable_cards(&self, coins: u8) -> impl Iterator<Item = CardName> + '_ {
-        self.supply_piles.iter().filter_map(move |s| {
-            s.pile
-                .peek()
-                .filter(|c| c.coins_cost <= coins)
-                .map(|c| c.name)
-        })
+        self.available_cards(move |c| c.coins_cost <= coins)
+    }
+
+    /** The names of the top cards of each pile which match `filter` */
+    pub fn available_cards<'s>(
+        &'s self,
+        filter: impl Fn(&Card) -> bool + 's,
+    ) -> impl Iterator<Item = CardName> + 's {
+        self.supply_piles
+            .iter()
+            .filter_map(move |s| s.pile.peek().filter(|c| filter(c)).map(|c| c.name))
     }
 
     /** Note that this looks up piles by the current top card
      * (matching what's returned from `buyable_cards`)
      * instead of using the original pile name.
      */
-    fn supply_pile_for(&mut self, card: CardName) -> Option<&mut CardPile> {
+    fn supply_pile_for(&mut self, card: CardName) -> Option<&mut NamedCardPile> {
         self.supply_piles
             .iter_mut()
             .find(|s| s.pile.peek().map(|c| c.name) == Some(card))
-            .map(|p| &mut p.pile)
     }
 
+    /** Gaining from an empty pile (eg when Witch runs out of curses) just does nothing */
+    #[cfg(test)]
     pub fn take_one(&mut self, card: CardName) -> Option<Card> {
-        self.take_up_to_n(card, 1).into_iter().next()
+        self.take_from_pile(card).map(|(_, card)| card)
+    }
+
+    /** Like `take_one`, but also says where the card was taken from */
+    pub fn take_from_pile(&mut self, card: CardName) -> Option<(Location, Card)> {
+        let NamedCardPile { name, pile } = self.supply_pile_for(card)?;
+        let location = Location::new(Place::Supply(*name), pile.len() - 1);
+        let card = pile.take_up_to_n(1).into_iter().next()?;
+        Some((location, card))
+    }
+
+    pub fn inspect(&self) -> impl Iterator<Item = &Card> + '_ {
+        self.supply_piles.iter().flat_map(|s| s.pile.iter())
+    }
+
+    /** Each pile's original name and its cards, top card last */
+    pub fn snapshot(&self) -> Vec<(CardName, Vec<CardName>)> {
+        self.supply_piles
+            .iter()
+            .map(|s| (s.name, s.pile.iter().map(|c| c.name).collect()))
+            .collect()
+    }
+
+    /** Unlike `add`, this keeps track of piles that have already run out */
+    pub fn add_named(&mut self, name: CardName, cards: Vec<Card>) {
+        self.supply_piles.push(NamedCardPile {
+            name,
+            pile: cards.into(),
+        });
     }
 
     pub fn add(&mut self, vec: Vec<Card>) {
@@ -66,11 +131,23 @@ impl Supply {
         let name = pile.peek().expect("Can't add empty pile").name;
         self.supply_piles.push(NamedCardPile { name, pile });
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::cards::{CardNames, Cards};
+
+    #[test]
+    fn taking_from_an_empty_pile_gives_nothing() {
+        let mut supply = Supply::new();
+        supply.add(vec![Cards::curse()]);
 
-    pub fn take_up_to_n(&mut self, card: CardName, n: usize) -> Vec<Card> {
-        let pile = self
-            .supply_pile_for(card)
-            .expect(format!("Missing supply pile for {:?}", card).as_str());
-        pile.take_up_to_n(n)
+        assert!(supply.take_one(CardNames::CURSE).is_some());
+        assert!(supply.take_one(CardNames::CURSE).is_none());
+        assert_eq!(
+            vec![CardNames::CURSE],
+            supply.empty_supply_piles().collect::<Vec<_>>()
+        );
     }
 }
@@ -0,0 +1,25 @@
+use super::cards::Card;
+
+/** The trash is shared by all players, in the same way as the supply */
+#[derive(Debug)]
+pub struct Trash {
+    cards: Vec<Card>,
+}
+
+impl Trash {
+    pub fn new() -> Self {
+        Trash { cards: vec![] }
+    }
+
+    pub fn add(&mut self, card: Card) {
+        self.cards.push(card)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cards.len()
+    }
+
+    pub fn inspect(&self) -> impl Iterator<Item = &Card> + '_ {
+        self.cards.iter()
+    }
+}
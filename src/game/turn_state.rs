@@ -0,0 +1,232 @@
+use derive_more::Constructor;
+use itertools::Itertools;
+
+use super::{
+    cards::{Card, CardName, CardTypes},
+    effects::{CardEffect, Reaction},
+    history::{Location, MoveReason, Place, StateChange},
+    logs::{GameEvent, GameLog},
+    observation::{Observation, PlayerObservation},
+    play_area::PlayArea,
+    player_counters::PlayerCounters,
+    players::{Agent2, AgentQuestion, ChoiceRequest, TurnStateStackEntry},
+    supply::Supply,
+    trash::Trash,
+};
+
+/** Another player at the table, who might get affected by an attack */
+#[derive(Debug, Constructor)]
+pub struct Opponent<'t, 'a> {
+    name: &'a str,
+    area: &'t mut PlayArea<'a>,
+    agent: &'t mut dyn Agent2,
+}
+
+/** Everything that resolving a card needs to know about the turn in progress,
+ * apart from the player's own cards (since the PlayArea gets borrowed separately)
+ */
+#[derive(Debug)]
+pub struct TurnState<'t, 'a> {
+    pub counters: PlayerCounters,
+    pub log: &'t GameLog,
+    pub supply: &'t mut Supply,
+    pub trash: &'t mut Trash,
+    /** Which round of the game this is */
+    turn: u8,
+    agent: &'t mut dyn Agent2,
+    /** In turn order, starting from the player to the left */
+    opponents: Vec<Opponent<'t, 'a>>,
+    /** Anyone at the table who isn't us or one of `opponents` (ie when we're
+     * being attacked), as they were when we started being asked things
+     */
+    bystanders: Vec<PlayerObservation>,
+    stack: Vec<TurnStateStackEntry>,
+    /** Effects waiting for a card to be played for the first time this turn */
+    first_play_bonuses: Vec<(CardName, CardEffect)>,
+}
+
+impl<'t, 'a> TurnState<'t, 'a> {
+    pub fn new(
+        log: &'t GameLog,
+        agent: &'t mut dyn Agent2,
+        supply: &'t mut Supply,
+        trash: &'t mut Trash,
+        opponents: Vec<Opponent<'t, 'a>>,
+        turn: u8,
+    ) -> Self {
+        TurnState {
+            counters: PlayerCounters::new_turn(),
+            log,
+            supply,
+            trash,
+            turn,
+            agent,
+            opponents,
+            bystanders: vec![],
+            stack: vec![],
+            first_play_bonuses: vec![],
+        }
+    }
+
+    pub fn push(&mut self, entry: TurnStateStackEntry) {
+        self.stack.push(entry);
+    }
+
+    pub fn pop(&mut self) {
+        self.stack
+            .pop()
+            .expect("BUG: popped more turn state entries than were pushed");
+    }
+
+    pub fn add_first_play_bonus(&mut self, card: CardName, effect: CardEffect) {
+        self.first_play_bonuses.push((card, effect));
+    }
+
+    /** Bonuses only apply once, so this removes them */
+    pub fn take_first_play_bonuses(&mut self, card: CardName) -> Vec<CardEffect> {
+        let (matching, rest) = std::mem::take(&mut self.first_play_bonuses)
+            .into_iter()
+            .partition(|(c, _)| *c == card);
+        self.first_play_bonuses = rest;
+        matching.into_iter().map(|(_, effect)| effect).collect()
+    }
+
+    /** Trashes a card that's already been taken from `from` */
+    pub fn trash_card(&mut self, card: Card, from: Location) {
+        let name = card.name;
+        if self.log.is_recording_changes() {
+            self.log.change(StateChange::Move {
+                reason: MoveReason::Trash,
+                card: name,
+                from,
+                to: Location::new(Place::Trash, self.trash.len()),
+            });
+        }
+        self.trash.add(card);
+        self.log.record(GameEvent::Trashed(name));
+    }
+
+    /** The card whose effects are currently being resolved, if any */
+    pub fn current_card(&self) -> Option<CardName> {
+        self.stack.iter().rev().find_map(|e| match e {
+            TurnStateStackEntry::Card(name) => Some(*name),
+            _ => None,
+        })
+    }
+
+    /** What the player who owns `area` can see right now */
+    fn observe(&self, area: &PlayArea) -> Observation {
+        let mut players = self
+            .bystanders
+            .iter()
+            .cloned()
+            .chain(self.opponents.iter().map(|o| o.area.public_info()))
+            .chain([area.public_info()])
+            .collect_vec();
+        players.sort_unstable_by_key(|p| p.seat);
+        let mut deck = area.inspect_deck().map(|c| c.name).collect_vec();
+        deck.sort_unstable();
+        Observation {
+            turn: self.turn,
+            seat: area.seat(),
+            hand: area.inspect_hand().map(|c| c.name).collect(),
+            deck,
+            discard: area.inspect_discard().map(|c| c.name).collect(),
+            in_play: area.inspect_in_play().map(|c| c.name).collect(),
+            counters: self.counters.clone(),
+            supply: self.supply.observe(),
+            players,
+        }
+    }
+
+    /** Asks the agent a question, making sure we get a valid answer back.
+     * `area` belongs to whoever is being asked, so they can see their own cards.
+     */
+    pub fn request_choice(&mut self, area: &PlayArea, question: AgentQuestion) -> Box<[CardName]> {
+        // no point bothering the agent if there's nothing to choose
+        let (_, max) = question.bounds();
+        if max == 0 || question.options().is_empty() {
+            return Box::new([]);
+        }
+
+        let request = ChoiceRequest::new(&self.stack, question);
+        let observation = self.observe(area);
+        let response = self.agent.request_choice(&request, &observation);
+        match request.validate(&response) {
+            Ok(()) => response.0,
+            Err(e) => {
+                self.log.record(GameEvent::InvalidChoice(e));
+                request.question().default_response().0
+            }
+        }
+    }
+
+    /** Lets `attack` do something to each other player in turn order. Any
+     * questions it asks get sent to the attacked player's agent rather than ours.
+     *
+     * Each player gets a chance to reveal reaction cards first, which might
+     * mean they don't get attacked at all.
+     */
+    pub fn attack_each_opponent(
+        &mut self,
+        attacker: &PlayArea,
+        mut attack: impl FnMut(&mut PlayArea<'a>, &mut TurnState<'_, 'a>),
+    ) {
+        let mut stack = self.stack.clone();
+        stack.push(TurnStateStackEntry::Attacked);
+        let attacker = attacker.public_info();
+
+        for i in 0..self.opponents.len() {
+            // everyone else might have been affected by the attack already
+            let bystanders = self
+                .opponents
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, o)| o.area.public_info())
+                .chain([attacker.clone()])
+                .collect();
+            let opponent = &mut self.opponents[i];
+            let _span = self.log.enter_attacked(opponent.name);
+            let mut attacked = TurnState {
+                counters: PlayerCounters::new_turn(),
+                log: self.log,
+                supply: self.supply,
+                trash: self.trash,
+                turn: self.turn,
+                agent: opponent.agent,
+                opponents: vec![],
+                bystanders,
+                stack: stack.clone(),
+                first_play_bonuses: vec![],
+            };
+
+            let reactions = opponent
+                .area
+                .inspect_hand()
+                .filter(|c| c.get_types().any(|t| t == CardTypes::REACTION))
+                .map(|c| c.name)
+                .collect();
+            let revealed =
+                attacked.request_choice(opponent.area, AgentQuestion::RevealReactions(reactions));
+            let mut unaffected = false;
+            for name in revealed.iter() {
+                self.log.record(GameEvent::ReactionRevealed(*name));
+                let reaction = opponent
+                    .area
+                    .inspect_hand()
+                    .find(|c| c.name == *name)
+                    .and_then(|c| c.reaction);
+                if let Some(Reaction::BlockAttack) = reaction {
+                    unaffected = true;
+                }
+            }
+
+            if unaffected {
+                self.log.record(GameEvent::UnaffectedByAttack);
+            } else {
+                attack(opponent.area, &mut attacked);
+            }
+        }
+    }
+}
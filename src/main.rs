@@ -1,41 +1,617 @@
-use std::{rc::Rc, cmp::Ordering};
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    process::ExitCode,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use clap::{Parser, ValueEnum};
+use itertools::Itertools;
 
-use crate::game::{
-    logs::{GameLog, NullLog},
-    players::Agents,
-    shuffler::RandomShuffler,
-    Game,
+use cards::{
+    game::{
+        card_registry::CardRegistry,
+        kingdom::{Kingdom, KingdomConstraints, KingdomError, PRESETS},
+        logs::{ConsoleLog, GameLog, JsonLog, NullLog, SharedOutput},
+        players::{
+            human::HumanAgent,
+            remote::{echo_bot, IllegalMovePolicy, RemoteAgent},
+            Agent2, Agents,
+        },
+        Game,
+    },
+    simulation::{
+        replay::GameRecording,
+        stats::{BatchStats, Z_95},
+        tournament::{Tournament, TournamentResults},
+        GameRecord, LogFactory, Simulation, SimulationResults,
+    },
 };
 
-mod game;
-
-fn main() {
-    let shuffler = RandomShuffler::unseeded();
-    let mut p1_wins = 0;
-    let mut p2_wins = 0;
-    let mut draws = 0;
-
-    for _ in 0..1000 {
-        let log = GameLog::new(Rc::new(NullLog::new()));
-        let mut game = Game::new(log);
-        let mut player_1 = Agents::simple_big_money();
-        let mut player_2 = Agents::big_money_splash_smithys();
-        game.add_player("P1 [SBM]", &mut player_1, &shuffler);
-        game.add_player("P2 [BMS]", &mut player_2, &shuffler);
-        game.populate_basic_kingdom();
-        game.populate_prosperous_kingdom();
-        game.populate_some_actions();
-
-        let results = game.play_to_end();
-        // TODO: figure out some nicer way to get at these results
-        let p1_score = results.0.get(0).unwrap().score;
-        let p2_score = results.0.get(1).unwrap().score;
-        match p1_score.cmp(&p2_score) {
-            Ordering::Greater => p1_wins += 1,
-            Ordering::Equal => draws += 1,
-            Ordering::Less => p2_wins += 1,
-        }
-    }
-
-    dbg!(p1_wins, p2_wins, draws);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum BasePiles {
+    /** Treasures, victory cards and curses */
+    Basic,
+    /** Platinum and Colony */
+    Prosperous,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum IllegalMoves {
+    /** Use the default answer, and carry on asking the agent */
+    Default,
+    /** Use default answers for the rest of the game */
+    Disconnect,
+    /** Stop everything */
+    Abort,
+}
+
+impl From<IllegalMoves> for IllegalMovePolicy {
+    fn from(moves: IllegalMoves) -> Self {
+        match moves {
+            IllegalMoves::Default => IllegalMovePolicy::PlayDefault,
+            IllegalMoves::Disconnect => IllegalMovePolicy::Disconnect,
+            IllegalMoves::Abort => IllegalMovePolicy::Abort,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogLevel {
+    Null,
+    Console,
+    /// Newline-delimited JSON, one object per event or span enter/exit
+    Json,
+}
+
+/// Plays lots of games of Dominion between computer players and reports who won
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// Agents to seat at the table, in turn order (see --list-agents).
+    /// Defaults to simple_big_money against big_money_splash_smithys,
+    /// or every agent for a tournament.
+    ///
+    /// Agents can also be remote, speaking JSON lines: "cmd:<command line>"
+    /// starts a process for each game, and "tcp:<host:port>" connects to one.
+    /// Each gets started once beforehand too, to check that it can be started
+    /// before any games begin.
+    agents: Vec<String>,
+
+    /// Play every agent against every other (in both seat orders) instead,
+    /// with --games games for each seat order
+    #[arg(long)]
+    tournament: bool,
+
+    /// How many players to seat. If this is more than the number of agents
+    /// given, the agents get repeated in order
+    #[arg(short, long)]
+    players: Option<usize>,
+
+    #[arg(short = 'n', long, default_value_t = 1000)]
+    games: u32,
+
+    /// Seed for shuffling, so that a run can be reproduced exactly
+    #[arg(short, long)]
+    seed: Option<u64>,
+
+    /// Sets of non-kingdom supply piles to add
+    #[arg(short, long, value_delimiter = ',', default_values = ["basic", "prosperous"])]
+    base: Vec<BasePiles>,
+
+    /// Named set of kingdom cards (see --list-presets). Defaults to "First Game",
+    /// unless --random-kingdom or --cards are given
+    #[arg(long, conflicts_with = "random_kingdom")]
+    preset: Option<String>,
+
+    /// Pick 10 kingdom cards at random (based on --seed)
+    #[arg(short, long)]
+    random_kingdom: bool,
+
+    /// Random kingdoms must have a card that gives +Buy
+    #[arg(long, requires = "random_kingdom")]
+    require_plus_buy: bool,
+
+    /// Random kingdoms can't have attacks
+    #[arg(long, requires = "random_kingdom")]
+    no_attacks: bool,
+
+    /// Random kingdoms must have cards costing 2-3, 4 and 5+
+    #[arg(long, requires = "random_kingdom")]
+    cost_spread: bool,
+
+    /// Extra kingdom cards to add a pile of each, by name (eg "Throne Room,Market")
+    #[arg(short, long, value_delimiter = ',')]
+    cards: Vec<String>,
+
+    /// File of extra card definitions, which --cards can then refer to
+    #[arg(long)]
+    card_file: Option<String>,
+
+    #[arg(short, long, value_enum, default_value_t = LogLevel::Null)]
+    log: LogLevel,
+
+    /// Write the --log json output here, rather than mixing it in with the summary
+    #[arg(long, requires = "log")]
+    log_file: Option<String>,
+
+    /// How many games to play at once (defaults to one per core, or 1 when logging)
+    #[arg(short, long)]
+    threads: Option<usize>,
+
+    /// Instead of a batch of games, play just one (see --record-game) and save
+    /// every shuffle and decision to this file, so it can be replayed exactly
+    #[arg(long, conflicts_with = "tournament")]
+    record: Option<String>,
+
+    /// Which game of the batch to record, counting from 0
+    #[arg(long, default_value_t = 0, requires = "record")]
+    record_game: u32,
+
+    /// Take the first seat yourself, and play a game at the terminal against
+    /// the given agents (big_money_splash_smithys by default)
+    #[arg(long, conflicts_with_all = ["tournament", "record"])]
+    play: bool,
+
+    /// Play back a game saved with --record (use --log to see what happened)
+    #[arg(long, conflicts_with_all = ["record", "tournament", "play"])]
+    replay: Option<String>,
+
+    /// How long remote agents get to make each decision, in milliseconds
+    #[arg(long, default_value_t = 5000)]
+    remote_timeout: u64,
+
+    /// What to do when a remote agent doesn't answer in time, or answers
+    /// with something it's not allowed to do
+    #[arg(long, value_enum, default_value_t = IllegalMoves::Default)]
+    illegal_moves: IllegalMoves,
+
+    /// Act as a remote agent that always picks as little as it can, reading
+    /// requests from stdin and answering on stdout
+    #[arg(long)]
+    echo_bot: bool,
+
+    /// Print the names of the available agents and exit
+    #[arg(long)]
+    list_agents: bool,
+
+    /// Print the kingdom presets and exit
+    #[arg(long)]
+    list_presets: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.list_agents {
+        for name in Agents::NAMES {
+            println!("{}", name);
+        }
+        return ExitCode::SUCCESS;
+    }
+    if args.echo_bot {
+        return match echo_bot(io::stdin().lock(), io::stdout().lock()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    if args.list_presets {
+        for (name, cards) in PRESETS {
+            println!("{}: {}", name, cards.join(", "));
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut registry = CardRegistry::with_builtin_cards();
+    if let Some(path) = &args.card_file {
+        registry.load_file(path)?;
+    }
+    for card in &args.cards {
+        registry.name(card)?;
+    }
+    // every game's JSON log goes through this, even when they're on different threads
+    let log_output: SharedOutput = match &args.log_file {
+        Some(path) => Arc::new(Mutex::new(BufWriter::new(File::create(path)?))),
+        None => Arc::new(Mutex::new(io::stdout())),
+    };
+    if let Some(path) = &args.replay {
+        return replay(args, &registry, &log_output, path);
+    }
+
+    let agents = match (args.agents.is_empty(), args.tournament) {
+        (false, _) => args.agents.clone(),
+        (true, false) if args.play => vec!["big_money_splash_smithys".to_owned()],
+        (true, false) => vec![
+            "simple_big_money".to_owned(),
+            "big_money_splash_smithys".to_owned(),
+        ],
+        (true, true) => Agents::NAMES.iter().map(|&n| n.to_owned()).collect(),
+    };
+    for name in &agents {
+        if !is_agent(name) {
+            return Err(format!(
+                "there's no agent called {:?} (try one of {})",
+                name,
+                Agents::NAMES.join(", ")
+            )
+            .into());
+        }
+    }
+
+    // games start on other threads, so this is the last chance to report this
+    // nicely (dropping the agent straight away stops it again)
+    for name in agents.iter().unique() {
+        if let Some(Err(e)) = start_remote(name) {
+            return Err(format!("couldn't start {}: {}", name, e).into());
+        }
+    }
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let kingdom = choose_kingdom(args, &registry, seed)?;
+    if let Some(kingdom) = &kingdom {
+        println!("Kingdom: {}", kingdom);
+        println!();
+    }
+    let setup = Setup {
+        args,
+        registry: &registry,
+        kingdom: kingdom.as_ref(),
+        log_output,
+    };
+    if args.tournament {
+        run_tournament(&setup, agents, seed);
+    } else if args.play {
+        play(&setup, agents, seed)?;
+    } else {
+        run_simulation(&setup, agents, seed)?;
+    }
+    Ok(())
+}
+
+fn is_agent(name: &str) -> bool {
+    match (name.strip_prefix("cmd:"), name.strip_prefix("tcp:")) {
+        (Some(command), _) => !command.trim().is_empty(),
+        (_, Some(address)) => !address.is_empty(),
+        _ => Agents::by_name(name).is_some(),
+    }
+}
+
+/** None for the built-in agents */
+fn start_remote(name: &str) -> Option<io::Result<RemoteAgent>> {
+    if let Some(command) = name.strip_prefix("cmd:") {
+        Some(RemoteAgent::spawn_command_line(name, command))
+    } else {
+        name.strip_prefix("tcp:")
+            .map(|address| RemoteAgent::connect(name, address))
+    }
+}
+
+/** Remote agents get started (or connected to) here, so every game gets a fresh one */
+fn make_agent(name: &str, args: &Args) -> Box<dyn Agent2> {
+    let Some(remote) = start_remote(name) else {
+        return Agents::by_name(name).expect("BUG: agent names were checked above");
+    };
+    let mut agent = remote.unwrap_or_else(|e| {
+        // it could be started before any games began, so just play on without it
+        eprintln!("{}: couldn't start: {}", name, e);
+        RemoteAgent::new(name, io::empty(), io::sink())
+    });
+    agent.set_timeout(Duration::from_millis(args.remote_timeout));
+    agent.set_illegal_move_policy(args.illegal_moves.into());
+    agent.set_problem_reporter(|problem| eprintln!("{}", problem));
+    Box::new(agent)
+}
+
+/** Everything needed to set up the supply for each game */
+struct Setup<'a> {
+    args: &'a Args,
+    registry: &'a CardRegistry,
+    kingdom: Option<&'a Kingdom>,
+    log_output: SharedOutput,
+}
+
+/** None if we're not logging anything */
+fn log_factory<'a>(args: &Args, log_output: &'a SharedOutput) -> Option<LogFactory<'a>> {
+    match args.log {
+        LogLevel::Null => None,
+        LogLevel::Console => Some(Box::new(|_| Rc::new(ConsoleLog::new()))),
+        LogLevel::Json => Some(Box::new(move |seed| {
+            Rc::new(JsonLog::for_game(log_output, seed))
+        })),
+    }
+}
+
+fn choose_kingdom(
+    args: &Args,
+    registry: &CardRegistry,
+    seed: u64,
+) -> Result<Option<Kingdom>, KingdomError> {
+    if args.random_kingdom {
+        let constraints = KingdomConstraints {
+            require_plus_buy: args.require_plus_buy,
+            no_attacks: args.no_attacks,
+            cost_spread: args.cost_spread,
+        };
+        return Kingdom::random(registry, seed, constraints).map(Some);
+    }
+    match (&args.preset, args.cards.is_empty()) {
+        (Some(preset), _) => Kingdom::preset(registry, preset).map(Some),
+        (None, true) => Kingdom::preset(registry, "First Game").map(Some),
+        (None, false) => Ok(None),
+    }
+}
+
+fn run_simulation(
+    setup: &Setup,
+    agents: Vec<String>,
+    seed: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let args = setup.args;
+    let player_count = args.players.unwrap_or(agents.len());
+    check_player_count(player_count)?;
+    let all_seats_play_alike = agents.iter().all_equal();
+    let agent_names = agents.into_iter().cycle().take(player_count).collect_vec();
+    let player_names = agent_names
+        .iter()
+        .enumerate()
+        .map(|(i, agent)| format!("P{} [{}]", i + 1, agent))
+        .collect_vec();
+
+    let mut simulation = Simulation::new(args.games, seed);
+    for (player_name, agent_name) in player_names.iter().zip(agent_names) {
+        simulation.add_player(player_name, move || make_agent(&agent_name, args));
+    }
+    simulation.set_supply(|game| populate_supply(game, setup));
+    if let Some(make_log) = log_factory(args, &setup.log_output) {
+        // games running side by side would be impossible to read
+        simulation.set_log(make_log);
+        simulation.set_threads(1);
+    }
+    if let Some(threads) = args.threads {
+        simulation.set_threads(threads);
+    }
+
+    if let Some(path) = &args.record {
+        let recording = simulation.record_game(args.record_game);
+        recording.save(path)?;
+        println!("Recorded game {} to {}", args.record_game, path);
+        print_game(recording.players.iter().map(|p| &p.name), &recording.result);
+        return Ok(());
+    }
+
+    let results = simulation.run();
+    print_summary(&results, all_seats_play_alike);
+    Ok(())
+}
+
+fn replay(
+    args: &Args,
+    registry: &CardRegistry,
+    log_output: &SharedOutput,
+    path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let recording = GameRecording::load(path)?;
+    let log = match log_factory(args, log_output) {
+        Some(make_log) => make_log(recording.result.seed),
+        None => Rc::new(NullLog::new()),
+    };
+    recording.replay(registry, GameLog::new(log))?;
+    println!("Replayed {} and it played out the same way", path);
+    print_game(recording.players.iter().map(|p| &p.name), &recording.result);
+    Ok(())
+}
+
+/** One game with a person in the first seat, and the agents in the rest */
+fn play(setup: &Setup, agents: Vec<String>, seed: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let args = setup.args;
+    let player_count = args.players.unwrap_or(agents.len() + 1);
+    check_player_count(player_count)?;
+
+    let mut simulation = Simulation::new(1, seed);
+    simulation.add_player("You", || Box::new(HumanAgent::stdio()));
+    let opponents = agents.into_iter().cycle().take(player_count - 1);
+    for (i, agent_name) in opponents.enumerate() {
+        simulation.add_player(format!("P{} [{}]", i + 2, agent_name), move || {
+            make_agent(&agent_name, args)
+        });
+    }
+    simulation.set_supply(|game| populate_supply(game, setup));
+    // the log is how we find out what everyone else is doing
+    let make_log = log_factory(args, &setup.log_output)
+        .unwrap_or_else(|| Box::new(|_| Rc::new(ConsoleLog::new())));
+    simulation.set_log(make_log);
+    simulation.set_threads(1);
+
+    let results = simulation.run();
+    println!();
+    print_game(&results.seat_names, &results.games[0]);
+    Ok(())
+}
+
+fn check_player_count(player_count: usize) -> Result<(), String> {
+    if !(2..=6).contains(&player_count) {
+        return Err(format!("Dominion needs 2-6 players, not {}", player_count));
+    }
+    Ok(())
+}
+
+fn print_game<'a>(names: impl IntoIterator<Item = &'a String>, result: &GameRecord) {
+    println!("(seed {}, {})", result.seed, result.end_reason);
+    for (name, record) in names.into_iter().zip(&result.players) {
+        println!(
+            "  #{} {}: {} VP in {} turns",
+            record.placement, name, record.score, record.turns_taken
+        );
+    }
+}
+
+fn run_tournament(setup: &Setup, agents: Vec<String>, seed: u64) {
+    let args = setup.args;
+    let mut tournament = Tournament::new(args.games, seed);
+    for agent_name in agents {
+        tournament.add_entrant(agent_name.clone(), move || make_agent(&agent_name, args));
+    }
+    tournament.set_supply(|game| populate_supply(game, setup));
+    if let Some(make_log) = log_factory(args, &setup.log_output) {
+        tournament.set_log(make_log);
+        tournament.set_threads(1);
+    }
+    if let Some(threads) = args.threads {
+        tournament.set_threads(threads);
+    }
+
+    let results = tournament.run();
+    print_tournament(&results, seed);
+}
+
+fn populate_supply(game: &mut Game, setup: &Setup) {
+    for piles in &setup.args.base {
+        match piles {
+            BasePiles::Basic => game.populate_basic_kingdom(),
+            BasePiles::Prosperous => game.populate_prosperous_kingdom(),
+        }
+    }
+    if let Some(kingdom) = setup.kingdom {
+        game.populate_kingdom(setup.registry, kingdom)
+            .expect("BUG: kingdoms only have cards from the registry");
+    }
+    for card in &setup.args.cards {
+        // skip cards that are already in the kingdom, rather than making a second pile
+        let name = setup
+            .registry
+            .name(card)
+            .expect("BUG: card names were checked above");
+        if setup.kingdom.is_some_and(|k| k.cards.contains(&name)) {
+            continue;
+        }
+        game.populate_supply_from(setup.registry, card)
+            .expect("BUG: card names were checked above");
+    }
+}
+
+fn print_summary(results: &SimulationResults, all_seats_play_alike: bool) {
+    let stats = BatchStats::new(results);
+    let name_width = stats
+        .seats
+        .iter()
+        .map(|s| s.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(6);
+
+    println!(
+        "{} games (seed {}), lasting {:.1} turns on average (median {:.1})",
+        stats.games, results.seed, stats.average_game_length, stats.median_game_length
+    );
+    println!();
+    println!(
+        "{:<name_width$}  {:>6}  {:>6}  {:>6}  {:>6}  {:>13}  {:>6}  {:>6}  {:>6}",
+        "Player", "Wins", "Shared", "Losses", "Win %", "95% CI", "Avg VP", "Med VP", "Turns"
+    );
+    for seat in &stats.seats {
+        let (lo, hi) = seat.win_rate.wilson_interval(Z_95);
+        println!(
+            "{:<name_width$}  {:>6}  {:>6}  {:>6}  {:>6.1}  {:>13}  {:>6.1}  {:>6.1}  {:>6.1}",
+            seat.name,
+            seat.wins,
+            seat.shared_wins,
+            seat.losses,
+            100.0 * seat.win_rate.rate(),
+            format!("{:.1}-{:.1}", 100.0 * lo, 100.0 * hi),
+            seat.average_score,
+            seat.median_score,
+            seat.average_turns,
+        );
+    }
+    println!();
+    // with different strategies in each seat this would mostly measure the first strategy
+    if all_seats_play_alike {
+        println!(
+            "First player advantage: {:+.1}% over a fair share",
+            100.0 * stats.first_player_advantage()
+        );
+    }
+    if stats.seats.len() == 2 {
+        let test = stats.compare_seats(0, 1);
+        println!(
+            "Head to head: z = {:.2}, p = {:.4} ({})",
+            test.z,
+            test.p_value,
+            if test.is_significant(0.05) {
+                "significant at 5%"
+            } else {
+                "could be noise"
+            }
+        );
+    }
+    println!();
+    println!("Games ended because:");
+    for (reason, count) in &stats.end_reasons {
+        println!("  {:>6}  {}", count, reason);
+    }
+}
+
+fn print_tournament(results: &TournamentResults, seed: u64) {
+    let ranking = results.ranking();
+    let name_width = results
+        .names
+        .iter()
+        .map(|n| n.len())
+        .max()
+        .unwrap_or(0)
+        .max(6);
+
+    println!("Tournament (seed {})", seed);
+    println!();
+    println!(
+        "{:>4}  {:<name_width$}  {:>6}  {:>6}  {:>6}",
+        "Rank", "Agent", "Rating", "Games", "Win %"
+    );
+    for (rank, &entrant) in ranking.iter().enumerate() {
+        let games = results.total_games(entrant);
+        println!(
+            "{:>4}  {:<name_width$}  {:>6.0}  {:>6}  {:>6.1}",
+            rank + 1,
+            results.names[entrant],
+            results.ratings[entrant],
+            games,
+            100.0 * results.total_points(entrant) / f64::from(games.max(1)),
+        );
+    }
+
+    // rows are how often that agent beat each column's agent
+    println!();
+    let label_width = name_width + 4;
+    print!("{:<label_width$}", "Win % vs");
+    for column in 1..=ranking.len() {
+        print!("  {:>5}", format!("#{}", column));
+    }
+    println!();
+    for (rank, &row) in ranking.iter().enumerate() {
+        print!(
+            "{:<name_width$}",
+            format!("#{} {}", rank + 1, results.names[row])
+        );
+        for &column in &ranking {
+            match results.win_rate(row, column) {
+                Some(rate) => print!("  {:>5.1}", 100.0 * rate),
+                None => print!("  {:>5}", "-"),
+            }
+        }
+        println!();
+    }
 }
@@ -0,0 +1,344 @@
+pub mod replay;
+pub mod stats;
+pub mod stepped;
+pub mod tournament;
+
+use std::{
+    collections::BTreeMap,
+    rc::Rc,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use self::replay::{GameRecording, PlayerRecording, RecordingAgent, RecordingShuffler};
+use crate::game::{
+    logs::{GameLog, GameLogInner, NullLog},
+    players::Agent2,
+    results::{GameEndReason, PlayerOutcome, PlayerResults},
+    shuffler::RandomShuffler,
+    Game,
+};
+
+/** Makes a fresh agent for each game, since agents can keep track of things between turns */
+pub type AgentFactory<'s> = Box<dyn Fn() -> Box<dyn Agent2> + Sync + 's>;
+/** Adds supply piles to a freshly-created game */
+pub type SupplySetup<'s> = Box<dyn Fn(&mut Game) + Sync + 's>;
+/** Makes a log for each game (given the game's seed), on whichever thread plays it */
+pub type LogFactory<'s> = Box<dyn Fn(u64) -> Rc<dyn GameLogInner> + Sync + 's>;
+
+struct Seat<'s> {
+    name: String,
+    make_agent: AgentFactory<'s>,
+}
+
+/** Plays lots of games with the same setup, spread across threads.
+ *
+ * `Game` and everything it borrows isn't `Send`, so rather than moving games
+ * between threads, each thread builds its own games from the factories here.
+ * Every game gets its own seed derived from the simulation seed and the game's
+ * index, so the results don't depend on how many threads there are.
+ */
+pub struct Simulation<'s> {
+    seats: Vec<Seat<'s>>,
+    setup_supply: SupplySetup<'s>,
+    make_log: LogFactory<'s>,
+    games: u32,
+    seed: u64,
+    threads: usize,
+}
+
+impl<'s> Simulation<'s> {
+    pub fn new(games: u32, seed: u64) -> Self {
+        Simulation {
+            seats: vec![],
+            setup_supply: Box::new(|game| game.populate_basic_kingdom()),
+            make_log: Box::new(|_| Rc::new(NullLog::new())),
+            games,
+            seed,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    pub fn add_player(
+        &mut self,
+        name: impl Into<String>,
+        make_agent: impl Fn() -> Box<dyn Agent2> + Sync + 's,
+    ) {
+        self.seats.push(Seat {
+            name: name.into(),
+            make_agent: Box::new(make_agent),
+        });
+    }
+
+    pub fn set_supply(&mut self, setup_supply: impl Fn(&mut Game) + Sync + 's) {
+        self.setup_supply = Box::new(setup_supply);
+    }
+
+    /** Each game gets its own log, created on whichever thread plays it. The
+     * game's seed gets passed in, so that logs can say which game they're for.
+     */
+    pub fn set_log(&mut self, make_log: impl Fn(u64) -> Rc<dyn GameLogInner> + Sync + 's) {
+        self.make_log = Box::new(make_log);
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn run(&self) -> SimulationResults {
+        let next_game = AtomicU32::new(0);
+        let mut games = std::thread::scope(|s| {
+            let workers = (0..self.threads)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut played = vec![];
+                        loop {
+                            let index = next_game.fetch_add(1, Ordering::Relaxed);
+                            if index >= self.games {
+                                break played;
+                            }
+                            played.push((index, self.play_one(index)));
+                        }
+                    })
+                })
+                .collect_vec();
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().expect("BUG: a simulation thread panicked"))
+                .collect_vec()
+        });
+        games.sort_by_key(|(index, _)| *index);
+
+        SimulationResults {
+            seat_names: self.seats.iter().map(|s| s.name.clone()).collect(),
+            seed: self.seed,
+            games: games.into_iter().map(|(_, g)| g).collect(),
+        }
+    }
+
+    fn play_one(&self, index: u32) -> GameRecord {
+        let seed = seed_for_game(self.seed, index);
+        let shuffler = RandomShuffler::new(seed);
+        let mut agents = self.seats.iter().map(|s| (s.make_agent)()).collect_vec();
+        let mut game = Game::new(GameLog::new((self.make_log)(seed)));
+        for (seat, agent) in self.seats.iter().zip(agents.iter_mut()) {
+            game.add_player(&seat.name, agent.as_mut(), &shuffler);
+        }
+        (self.setup_supply)(&mut game);
+
+        let results = game.play_to_end();
+        GameRecord::new(seed, &results)
+    }
+
+    /** Plays the same game as `run` would for this index, but keeps track of
+     * every shuffle and decision so that it can be replayed without the agents
+     */
+    pub fn record_game(&self, index: u32) -> GameRecording {
+        let seed = seed_for_game(self.seed, index);
+        let shuffler = RandomShuffler::new(seed);
+        // each player gets their own recording of the shared shuffler,
+        // which still gets called in the same order as it would have been
+        let shufflers = self
+            .seats
+            .iter()
+            .map(|_| RecordingShuffler::new(&shuffler))
+            .collect_vec();
+        let mut agents = self.seats.iter().map(|s| (s.make_agent)()).collect_vec();
+        let mut recorders = agents
+            .iter_mut()
+            .map(|a| RecordingAgent::new(a.as_mut()))
+            .collect_vec();
+
+        let mut game = Game::new(GameLog::new((self.make_log)(seed)));
+        for ((seat, agent), shuffler) in self.seats.iter().zip(&mut recorders).zip(&shufflers) {
+            game.add_player(&seat.name, agent, shuffler);
+        }
+        (self.setup_supply)(&mut game);
+        let supply = game.supply_piles();
+
+        let results = game.play_to_end();
+        let result = GameRecord::new(seed, &results);
+        drop(results);
+        drop(game);
+
+        let players = self
+            .seats
+            .iter()
+            .zip(recorders)
+            .zip(shufflers)
+            .map(|((seat, agent), shuffler)| PlayerRecording {
+                name: seat.name.clone(),
+                shuffles: shuffler.into_shuffles(),
+                decisions: agent.into_decisions(),
+            })
+            .collect();
+        GameRecording {
+            supply,
+            players,
+            result,
+        }
+    }
+}
+
+/** Mixes the game index into the simulation seed (using the SplitMix64 finalizer),
+ * so that neighbouring games don't get similar seeds
+ */
+pub fn seed_for_game(simulation_seed: u64, index: u32) -> u64 {
+    let mut z = simulation_seed.wrapping_add(u64::from(index).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/** How one player did in one game */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerRecord {
+    pub score: i16,
+    pub turns_taken: u8,
+    pub placement: usize,
+    pub outcome: PlayerOutcome,
+}
+
+/** Everything we keep from a game once it's over, since `PlayerResults` borrows the game */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub seed: u64,
+    /** In seating order */
+    pub players: Vec<PlayerRecord>,
+    pub end_reason: GameEndReason,
+}
+
+impl GameRecord {
+    fn new(seed: u64, results: &PlayerResults) -> Self {
+        GameRecord {
+            seed,
+            players: results
+                .players
+                .iter()
+                .map(|p| PlayerRecord {
+                    score: p.score,
+                    turns_taken: p.turns_taken,
+                    placement: results.placement(p),
+                    outcome: results
+                        .outcome_for(p.name)
+                        .expect("BUG: every player should have an outcome"),
+                })
+                .collect(),
+            end_reason: results.end_reason.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SimulationResults {
+    pub seat_names: Vec<String>,
+    pub seed: u64,
+    /** In the order they were created, which is also the order of their seeds */
+    pub games: Vec<GameRecord>,
+}
+
+impl SimulationResults {
+    pub fn seat_records(&self, seat: usize) -> impl Iterator<Item = &PlayerRecord> + '_ {
+        self.games.iter().map(move |g| &g.players[seat])
+    }
+
+    pub fn outcome_count(&self, seat: usize, outcome: PlayerOutcome) -> usize {
+        self.seat_records(seat)
+            .filter(|p| p.outcome == outcome)
+            .count()
+    }
+
+    pub fn average_score(&self, seat: usize) -> f64 {
+        self.average(self.seat_records(seat).map(|p| f64::from(p.score)))
+    }
+
+    pub fn average_turns(&self, seat: usize) -> f64 {
+        self.average(self.seat_records(seat).map(|p| f64::from(p.turns_taken)))
+    }
+
+    fn average(&self, values: impl Iterator<Item = f64>) -> f64 {
+        values.sum::<f64>() / self.games.len().max(1) as f64
+    }
+
+    /** Groups together games that ended for the same kind of reason,
+     * rather than listing every combination of empty piles separately
+     */
+    pub fn end_reasons(&self) -> BTreeMap<String, usize> {
+        let mut reasons = BTreeMap::new();
+        for game in &self.games {
+            let reason = match &game.end_reason {
+                GameEndReason::PilesEmptied(piles) => {
+                    format!("{} supply piles ran out", piles.len())
+                }
+                reason => reason.to_string(),
+            };
+            *reasons.entry(reason).or_default() += 1;
+        }
+        reasons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{card_registry::CardRegistry, players::Agents};
+
+    fn simulation(games: u32) -> Simulation<'static> {
+        let mut simulation = Simulation::new(games, 1234);
+        simulation.add_player("P1", || Box::new(Agents::big_money_witch()));
+        simulation.add_player("P2", || Box::new(Agents::simple_big_money()));
+        let registry = CardRegistry::with_builtin_cards();
+        simulation.set_supply(move |game| {
+            game.populate_basic_kingdom();
+            game.populate_supply_from(&registry, "Witch").unwrap();
+        });
+        simulation
+    }
+
+    #[test]
+    fn results_dont_depend_on_the_number_of_threads() {
+        let mut single = simulation(20);
+        single.set_threads(1);
+        let mut several = simulation(20);
+        several.set_threads(4);
+
+        assert_eq!(single.run().games, several.run().games);
+    }
+
+    #[test]
+    fn every_game_gets_a_different_seed() {
+        let results = simulation(20).run();
+
+        assert_eq!(20, results.games.len());
+        assert!(results.games.iter().map(|g| g.seed).all_unique());
+    }
+
+    #[test]
+    fn every_game_has_an_outcome_for_each_seat() {
+        let results = simulation(20).run();
+
+        let decided = |seat| {
+            [
+                PlayerOutcome::Win,
+                PlayerOutcome::SharedWin,
+                PlayerOutcome::Loss,
+            ]
+            .iter()
+            .map(|&o| results.outcome_count(seat, o))
+            .sum::<usize>()
+        };
+        assert_eq!(20, decided(0));
+        assert_eq!(20, decided(1));
+        assert!(results.average_score(0) > 0.0);
+        assert_eq!(20, results.end_reasons().values().sum::<usize>());
+    }
+
+    #[test]
+    fn game_seeds_are_derived_from_the_simulation_seed() {
+        assert_eq!(seed_for_game(1, 5), seed_for_game(1, 5));
+        assert_ne!(seed_for_game(1, 5), seed_for_game(2, 5));
+        assert_ne!(seed_for_game(1, 5), seed_for_game(1, 6));
+    }
+}
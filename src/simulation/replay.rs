@@ -0,0 +1,464 @@
+use std::{
+    cell::RefCell,
+    fmt::{Display, Formatter},
+    path::Path,
+};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    card_registry::{CardRegistry, CardRegistryError},
+    cards::{Card, CardName},
+    logs::GameLog,
+    observation::Observation,
+    players::{Agent2, ChoiceRequest, ChoiceResponse},
+    shuffler::{PredestinedShuffleError, PredestinedShuffler, Shuffler},
+    Game,
+};
+
+use super::GameRecord;
+
+/** One question an agent was asked, and what it answered */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Decision {
+    pub request: ChoiceRequest,
+    pub response: ChoiceResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerRecording {
+    pub name: String,
+    /** The order each of this player's shuffles came out in, in the order they were drawn */
+    pub shuffles: Vec<Vec<CardName>>,
+    pub decisions: Vec<Decision>,
+}
+
+/** Everything needed to play a game again exactly as it happened,
+ * without needing the agents or the seed that originally played it
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameRecording {
+    /** Each pile's name and size before anyone was dealt a hand */
+    pub supply: Vec<(CardName, usize)>,
+    /** In seating order */
+    pub players: Vec<PlayerRecording>,
+    /** How the game originally turned out */
+    pub result: GameRecord,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /** The recording uses a card that the registry doesn't know about */
+    Registry(CardRegistryError),
+    /** A player's `index`th question (counting from 1) wasn't the one that got
+     * recorded. Either side is None if the player was asked fewer questions.
+     */
+    Diverged {
+        player: String,
+        index: usize,
+        expected: Option<Box<ChoiceRequest>>,
+        actual: Option<Box<ChoiceRequest>>,
+    },
+    /** A shuffle wasn't of the cards that got recorded */
+    ShuffleDiverged(PredestinedShuffleError),
+    /** A player didn't shuffle as often as they did originally */
+    UnusedShuffles {
+        player: String,
+        remaining: usize,
+    },
+    /** Everything went as recorded, but the game still ended differently */
+    ResultDiverged {
+        expected: GameRecord,
+        actual: GameRecord,
+    },
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "couldn't read or write the recording: {}", e),
+            ReplayError::Json(e) => write!(f, "couldn't parse the recording: {}", e),
+            ReplayError::Registry(e) => e.fmt(f),
+            ReplayError::Diverged {
+                player,
+                index,
+                expected,
+                actual,
+            } => match (expected, actual) {
+                (Some(expected), Some(actual)) => write!(
+                    f,
+                    "replay diverged: question {} for {} should have been {:?} but was {:?}",
+                    index, player, expected, actual
+                ),
+                (None, Some(actual)) => write!(
+                    f,
+                    "replay diverged: {} was asked more questions than recorded, starting with {:?}",
+                    player, actual
+                ),
+                (_, None) => write!(
+                    f,
+                    "replay diverged: {} was only asked {} of the recorded questions",
+                    player,
+                    index - 1
+                ),
+            },
+            ReplayError::ShuffleDiverged(e) => write!(f, "replay diverged: {}", e),
+            ReplayError::UnusedShuffles { player, remaining } => write!(
+                f,
+                "replay diverged: {} shuffled {} fewer times than recorded",
+                player, remaining
+            ),
+            ReplayError::ResultDiverged { expected, actual } => write!(
+                f,
+                "replay diverged: the game ended {} rather than {}",
+                actual.end_reason, expected.end_reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl GameRecording {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        let json = serde_json::to_string(self).map_err(ReplayError::Json)?;
+        std::fs::write(path, json).map_err(ReplayError::Io)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let json = std::fs::read_to_string(path).map_err(ReplayError::Io)?;
+        serde_json::from_str(&json).map_err(ReplayError::Json)
+    }
+
+    /** Plays the recorded game again, with the recorded shuffles and decisions
+     * standing in for the original shuffler and agents (using a `PredestinedShuffler`
+     * for each player).
+     *
+     * If the replay stops following the recording (eg a player gets asked a
+     * question they weren't asked originally), then the game engine has changed
+     * behaviour since the recording was made. The first place that happened
+     * gets returned as an error, once the rest of the game has been played out
+     * with default answers.
+     */
+    pub fn replay(&self, registry: &CardRegistry, log: GameLog) -> Result<GameRecord, ReplayError> {
+        let divergence = RefCell::new(None);
+        let shufflers = self
+            .players
+            .iter()
+            .map(|p| {
+                ReplayShuffler::new(
+                    PredestinedShuffler::new(&p.name, p.shuffles.iter().cloned()),
+                    &divergence,
+                )
+            })
+            .collect_vec();
+        let mut agents = self
+            .players
+            .iter()
+            .map(|p| ScriptedAgent::new(p, &divergence))
+            .collect_vec();
+
+        let mut game = Game::new(log);
+        for ((player, agent), shuffler) in self.players.iter().zip(&mut agents).zip(&shufflers) {
+            game.add_player(&player.name, agent, shuffler);
+        }
+        for &(name, count) in &self.supply {
+            game.populate_pile(registry, name, count)
+                .map_err(ReplayError::Registry)?;
+        }
+
+        let results = game.play_to_end();
+        let replayed = GameRecord::new(self.result.seed, &results);
+        drop(results);
+        drop(game);
+
+        if let Some(error) = divergence.take() {
+            return Err(error);
+        }
+        for agent in &agents {
+            agent.check_finished()?;
+        }
+        for (player, shuffler) in self.players.iter().zip(&shufflers) {
+            let remaining = shuffler.script.remaining();
+            if remaining > 0 {
+                return Err(ReplayError::UnusedShuffles {
+                    player: player.name.clone(),
+                    remaining,
+                });
+            }
+        }
+        if self.result != replayed {
+            return Err(ReplayError::ResultDiverged {
+                expected: self.result.clone(),
+                actual: replayed,
+            });
+        }
+        Ok(replayed)
+    }
+}
+
+/** Remembers how each shuffle came out, so it can be replayed later */
+#[derive(Debug)]
+pub(super) struct RecordingShuffler<'s> {
+    inner: &'s dyn Shuffler<Card>,
+    shuffles: RefCell<Vec<Vec<CardName>>>,
+}
+impl<'s> RecordingShuffler<'s> {
+    pub fn new(inner: &'s dyn Shuffler<Card>) -> Self {
+        RecordingShuffler {
+            inner,
+            shuffles: vec![].into(),
+        }
+    }
+
+    pub fn into_shuffles(self) -> Vec<Vec<CardName>> {
+        self.shuffles.into_inner()
+    }
+}
+impl Shuffler<Card> for RecordingShuffler<'_> {
+    fn shuffle(&self, input: &mut Vec<Card>) -> Vec<Card> {
+        let shuffled = self.inner.shuffle(input);
+        // the deck is drawn from the end
+        self.shuffles
+            .borrow_mut()
+            .push(shuffled.iter().rev().map(|c| c.name).collect());
+        shuffled
+    }
+}
+
+/** Remembers every decision an agent makes, so it can be replayed later */
+#[derive(Debug)]
+pub(super) struct RecordingAgent<'a> {
+    inner: &'a mut dyn Agent2,
+    decisions: Vec<Decision>,
+}
+impl<'a> RecordingAgent<'a> {
+    pub fn new(inner: &'a mut dyn Agent2) -> Self {
+        RecordingAgent {
+            inner,
+            decisions: vec![],
+        }
+    }
+
+    pub fn into_decisions(self) -> Vec<Decision> {
+        self.decisions
+    }
+}
+impl Agent2 for RecordingAgent<'_> {
+    fn request_choice(
+        &mut self,
+        request: &ChoiceRequest,
+        observation: &Observation,
+    ) -> ChoiceResponse {
+        let response = self.inner.request_choice(request, observation);
+        self.decisions.push(Decision {
+            request: request.clone(),
+            response: response.clone(),
+        });
+        response
+    }
+}
+
+/** The first way a replay stopped following its recording, shared between
+ * every player's shuffler and agent. Once something has gone wrong, nothing
+ * else that happens means much, so everyone just plays out the game.
+ */
+type Divergence = RefCell<Option<ReplayError>>;
+
+fn diverge(divergence: &Divergence, error: ReplayError) {
+    divergence.borrow_mut().get_or_insert(error);
+}
+
+#[derive(Debug)]
+struct ReplayShuffler<'r> {
+    script: PredestinedShuffler,
+    divergence: &'r Divergence,
+}
+impl<'r> ReplayShuffler<'r> {
+    fn new(script: PredestinedShuffler, divergence: &'r Divergence) -> Self {
+        ReplayShuffler { script, divergence }
+    }
+}
+impl Shuffler<Card> for ReplayShuffler<'_> {
+    fn shuffle(&self, input: &mut Vec<Card>) -> Vec<Card> {
+        if self.divergence.borrow().is_none() {
+            match self.script.try_shuffle(input) {
+                Ok(shuffled) => return shuffled,
+                Err(e) => diverge(self.divergence, ReplayError::ShuffleDiverged(e)),
+            }
+        }
+        // (try_shuffle leaves the cards alone if it fails)
+        std::mem::take(input)
+    }
+}
+
+/** Gives the recorded answers, checking that it's being asked the recorded questions */
+#[derive(Debug)]
+struct ScriptedAgent<'r> {
+    player: &'r str,
+    decisions: std::slice::Iter<'r, Decision>,
+    answered: usize,
+    divergence: &'r Divergence,
+}
+impl<'r> ScriptedAgent<'r> {
+    fn new(recording: &'r PlayerRecording, divergence: &'r Divergence) -> Self {
+        ScriptedAgent {
+            player: &recording.name,
+            decisions: recording.decisions.iter(),
+            answered: 0,
+            divergence,
+        }
+    }
+
+    fn diverged(&self, expected: Option<&Decision>, actual: Option<&ChoiceRequest>) -> ReplayError {
+        ReplayError::Diverged {
+            player: self.player.to_owned(),
+            index: self.answered + 1,
+            expected: expected.map(|d| Box::new(d.request.clone())),
+            actual: actual.map(|r| Box::new(r.clone())),
+        }
+    }
+
+    fn check_finished(&self) -> Result<(), ReplayError> {
+        match self.decisions.clone().next() {
+            Some(decision) => Err(self.diverged(Some(decision), None)),
+            None => Ok(()),
+        }
+    }
+}
+impl Agent2 for ScriptedAgent<'_> {
+    fn request_choice(&mut self, request: &ChoiceRequest, _: &Observation) -> ChoiceResponse {
+        if self.divergence.borrow().is_none() {
+            match self.decisions.next() {
+                Some(decision) if decision.request == *request => {
+                    self.answered += 1;
+                    return decision.response.clone();
+                }
+                decision => diverge(self.divergence, self.diverged(decision, Some(request))),
+            }
+        }
+        request.question().default_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::{cards::CardNames, logs::tests::null_log, players::Agents},
+        simulation::Simulation,
+    };
+
+    fn simulation() -> Simulation<'static> {
+        let mut simulation = Simulation::new(10, 1234);
+        simulation.add_player("P1", || Box::new(Agents::big_money_witch()));
+        simulation.add_player("P2", || Box::new(Agents::chapel_big_money()));
+        simulation.set_supply(|game| {
+            let registry = CardRegistry::with_builtin_cards();
+            game.populate_basic_kingdom();
+            game.populate_supply_from(&registry, "Witch").unwrap();
+            game.populate_supply_from(&registry, "Chapel").unwrap();
+        });
+        simulation
+    }
+
+    #[test]
+    fn recording_a_game_doesnt_change_how_it_plays_out() {
+        let simulation = simulation();
+        let results = simulation.run();
+
+        let recording = simulation.record_game(3);
+
+        assert_eq!(results.games[3], recording.result);
+        assert!(recording.players.iter().all(|p| !p.decisions.is_empty()));
+    }
+
+    #[test]
+    fn a_recorded_game_replays_the_same_way() {
+        let recording = simulation().record_game(5);
+
+        let replayed = recording
+            .replay(&CardRegistry::with_builtin_cards(), null_log())
+            .unwrap();
+
+        assert_eq!(recording.result, replayed);
+    }
+
+    #[test]
+    fn recordings_survive_being_saved_and_loaded() {
+        let recording = simulation().record_game(0);
+        let path = std::env::temp_dir().join(format!("recording-{}.json", std::process::id()));
+
+        recording.save(&path).unwrap();
+        let loaded = GameRecording::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(recording, loaded.unwrap());
+    }
+
+    #[test]
+    fn replays_fail_if_a_decision_changes() {
+        let mut recording = simulation().record_game(1);
+        let decision = recording.players[0]
+            .decisions
+            .iter_mut()
+            .find(|d| !d.response.0.is_empty())
+            .unwrap();
+        decision.response = ChoiceResponse::none();
+
+        let result = recording.replay(&CardRegistry::with_builtin_cards(), null_log());
+
+        // (this might show up as a different shuffle before a different question)
+        let error = result.unwrap_err();
+        assert!(
+            error.to_string().starts_with("replay diverged:"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn replays_fail_if_a_shuffle_doesnt_match() {
+        let mut recording = simulation().record_game(1);
+        recording.players[1].shuffles[0][0] = CardNames::GOLD;
+
+        let result = recording.replay(&CardRegistry::with_builtin_cards(), null_log());
+
+        let error = result.unwrap_err();
+        assert!(matches!(error, ReplayError::ShuffleDiverged(_)));
+        assert!(error
+            .to_string()
+            .starts_with("replay diverged: shuffle 1 for P2 was of"));
+    }
+
+    #[test]
+    fn replays_fail_if_questions_are_left_over() {
+        let mut recording = simulation().record_game(2);
+        let extra = recording.players[0].decisions.last().unwrap().clone();
+        recording.players[0].decisions.push(extra);
+
+        let result = recording.replay(&CardRegistry::with_builtin_cards(), null_log());
+
+        match result {
+            Err(ReplayError::Diverged {
+                player,
+                expected: Some(_),
+                actual: None,
+                ..
+            }) => assert_eq!("P1", player),
+            other => panic!("expected a divergence, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn replays_need_every_card_in_the_registry() {
+        let recording = simulation().record_game(0);
+
+        let result = recording.replay(&CardRegistry::new(), null_log());
+
+        assert!(matches!(result, Err(ReplayError::Registry(_))));
+    }
+}
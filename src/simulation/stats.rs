@@ -0,0 +1,331 @@
+use std::collections::BTreeMap;
+
+use itertools::Itertools;
+
+use crate::game::results::PlayerOutcome;
+
+use super::SimulationResults;
+
+/** z-score for a 95% confidence level */
+pub const Z_95: f64 = 1.959_964;
+
+/** Some number of successes out of some number of trials. Shared wins count as
+ * half a success, so successes don't have to be whole numbers.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Proportion {
+    pub successes: f64,
+    pub trials: usize,
+}
+
+impl Proportion {
+    pub fn new(successes: f64, trials: usize) -> Self {
+        Proportion { successes, trials }
+    }
+
+    pub fn rate(&self) -> f64 {
+        if self.trials == 0 {
+            return 0.0;
+        }
+        self.successes / self.trials as f64
+    }
+
+    /** Wilson score interval, which behaves better than the usual normal
+     * approximation when the rate is close to 0 or 1 or there aren't many trials
+     */
+    pub fn wilson_interval(&self, z: f64) -> (f64, f64) {
+        if self.trials == 0 {
+            return (0.0, 1.0);
+        }
+        let n = self.trials as f64;
+        let p = self.rate();
+        let z2 = z * z;
+        let centre = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let spread = z / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+        ((centre - spread).max(0.0), (centre + spread).min(1.0))
+    }
+}
+
+/** The result of a two-sided test for whether a difference is just noise */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignificanceTest {
+    pub z: f64,
+    pub p_value: f64,
+}
+
+impl SignificanceTest {
+    fn from_z(z: f64) -> Self {
+        SignificanceTest {
+            z,
+            p_value: 2.0 * (1.0 - normal_cdf(z.abs())),
+        }
+    }
+
+    pub fn is_significant(&self, alpha: f64) -> bool {
+        self.p_value < alpha
+    }
+
+    /** For two strategies playing each other: of the games one of them won
+     * outright, did either win more often than a coin flip would?
+     */
+    pub fn sign_test(a_wins: usize, b_wins: usize) -> Self {
+        let decided = (a_wins + b_wins) as f64;
+        if decided == 0.0 {
+            return SignificanceTest::from_z(0.0);
+        }
+        SignificanceTest::from_z((a_wins as f64 - b_wins as f64) / decided.sqrt())
+    }
+
+    /** For win rates from two separate batches of games (eg each strategy
+     * against the same opponent), using the pooled two-proportion z-test
+     */
+    pub fn two_proportions(a: Proportion, b: Proportion) -> Self {
+        if a.trials == 0 || b.trials == 0 {
+            return SignificanceTest::from_z(0.0);
+        }
+        let (n_a, n_b) = (a.trials as f64, b.trials as f64);
+        let pooled = (a.successes + b.successes) / (n_a + n_b);
+        let standard_error = (pooled * (1.0 - pooled) * (1.0 / n_a + 1.0 / n_b)).sqrt();
+        if standard_error == 0.0 {
+            return SignificanceTest::from_z(0.0);
+        }
+        SignificanceTest::from_z((a.rate() - b.rate()) / standard_error)
+    }
+}
+
+/** Standard normal CDF, via the Abramowitz and Stegun approximation of erf
+ * (accurate to about 1e-7, which is plenty for p-values)
+ */
+pub fn normal_cdf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs() / std::f64::consts::SQRT_2);
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erf = 1.0 - poly * (-(x * x) / 2.0).exp();
+    if x >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+#[derive(Debug, Clone)]
+pub struct SeatStats {
+    pub name: String,
+    pub wins: usize,
+    pub shared_wins: usize,
+    pub losses: usize,
+    /** Shared wins count as half a win */
+    pub win_rate: Proportion,
+    pub average_score: f64,
+    pub median_score: f64,
+    pub average_turns: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchStats {
+    pub games: usize,
+    /** In seating order */
+    pub seats: Vec<SeatStats>,
+    /** How many rounds games lasted, ie the most turns anyone took */
+    pub average_game_length: f64,
+    pub median_game_length: f64,
+    pub end_reasons: BTreeMap<String, usize>,
+    /** `head_to_head[a][b]` is how many games seat `a` finished ahead of seat `b` */
+    pub head_to_head: Vec<Vec<usize>>,
+}
+
+impl BatchStats {
+    pub fn new(results: &SimulationResults) -> Self {
+        let games = results.games.len();
+        let seats: Vec<SeatStats> = results
+            .seat_names
+            .iter()
+            .enumerate()
+            .map(|(seat, name)| {
+                let count = |outcome| results.outcome_count(seat, outcome);
+                let (wins, shared_wins, losses) = (
+                    count(PlayerOutcome::Win),
+                    count(PlayerOutcome::SharedWin),
+                    count(PlayerOutcome::Loss),
+                );
+                let scores = results
+                    .seat_records(seat)
+                    .map(|p| f64::from(p.score))
+                    .collect_vec();
+                SeatStats {
+                    name: name.clone(),
+                    wins,
+                    shared_wins,
+                    losses,
+                    win_rate: Proportion::new(wins as f64 + shared_wins as f64 / 2.0, games),
+                    average_score: mean(&scores),
+                    median_score: median(scores),
+                    average_turns: results.average_turns(seat),
+                }
+            })
+            .collect();
+        let lengths = results
+            .games
+            .iter()
+            .map(|g| f64::from(g.players.iter().map(|p| p.turns_taken).max().unwrap_or(0)))
+            .collect_vec();
+        let mut head_to_head = vec![vec![0; seats.len()]; seats.len()];
+        for game in &results.games {
+            for (a, pa) in game.players.iter().enumerate() {
+                for (b, pb) in game.players.iter().enumerate() {
+                    if pa.placement < pb.placement {
+                        head_to_head[a][b] += 1;
+                    }
+                }
+            }
+        }
+
+        BatchStats {
+            games,
+            seats,
+            average_game_length: mean(&lengths),
+            median_game_length: median(lengths),
+            end_reasons: results.end_reasons(),
+            head_to_head,
+        }
+    }
+
+    /** How much more often the first seat wins than its fair share.
+     *
+     * This only says something about turn order if every seat plays the same
+     * strategy, otherwise it mostly measures how good the first strategy is.
+     */
+    pub fn first_player_advantage(&self) -> f64 {
+        match self.seats.first() {
+            Some(first) => first.win_rate.rate() - 1.0 / self.seats.len() as f64,
+            None => 0.0,
+        }
+    }
+
+    /** Whether one seat finished ahead of another more often than chance would explain */
+    pub fn compare_seats(&self, a: usize, b: usize) -> SignificanceTest {
+        SignificanceTest::sign_test(self.head_to_head[a][b], self.head_to_head[b][a])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::results::GameEndReason,
+        simulation::{GameRecord, PlayerRecord},
+    };
+
+    fn assert_close(expected: f64, actual: f64) {
+        assert!(
+            (expected - actual).abs() < 1e-4,
+            "expected {} but got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn wilson_interval_matches_known_values() {
+        let (lo, hi) = Proportion::new(50.0, 100).wilson_interval(Z_95);
+        assert_close(0.4038, lo);
+        assert_close(0.5962, hi);
+
+        let (lo, hi) = Proportion::new(0.0, 10).wilson_interval(Z_95);
+        assert_close(0.0, lo);
+        assert_close(0.2775, hi);
+    }
+
+    #[test]
+    fn normal_cdf_matches_known_values() {
+        assert_close(0.5, normal_cdf(0.0));
+        assert_close(0.975, normal_cdf(Z_95));
+        assert_close(0.0228, normal_cdf(-2.0));
+    }
+
+    #[test]
+    fn sign_test_spots_lopsided_results() {
+        let test = SignificanceTest::sign_test(60, 40);
+        assert_close(2.0, test.z);
+        assert_close(0.0455, test.p_value);
+        assert!(test.is_significant(0.05));
+
+        assert!(!SignificanceTest::sign_test(52, 48).is_significant(0.05));
+    }
+
+    #[test]
+    fn two_proportion_test_matches_a_worked_example() {
+        let test = SignificanceTest::two_proportions(
+            Proportion::new(45.0, 100),
+            Proportion::new(30.0, 100),
+        );
+        assert_close(2.1909, test.z);
+    }
+
+    fn game(scores: &[(i16, u8, usize, PlayerOutcome)]) -> GameRecord {
+        GameRecord {
+            seed: 0,
+            players: scores
+                .iter()
+                .map(|&(score, turns_taken, placement, outcome)| PlayerRecord {
+                    score,
+                    turns_taken,
+                    placement,
+                    outcome,
+                })
+                .collect(),
+            end_reason: GameEndReason::TurnLimitReached(100),
+        }
+    }
+
+    #[test]
+    fn batch_stats_summarise_each_seat() {
+        use PlayerOutcome::*;
+        let results = SimulationResults {
+            seat_names: vec!["P1".to_owned(), "P2".to_owned()],
+            seed: 0,
+            games: vec![
+                game(&[(30, 20, 1, Win), (20, 19, 2, Loss)]),
+                game(&[(25, 18, 1, SharedWin), (25, 18, 1, SharedWin)]),
+                game(&[(10, 15, 2, Loss), (40, 15, 1, Win)]),
+                game(&[(35, 16, 1, Win), (30, 15, 2, Loss)]),
+            ],
+        };
+
+        let stats = BatchStats::new(&results);
+
+        let p1 = &stats.seats[0];
+        assert_eq!((2, 1, 1), (p1.wins, p1.shared_wins, p1.losses));
+        assert_close(0.625, p1.win_rate.rate());
+        assert_close(25.0, p1.average_score);
+        assert_close(27.5, p1.median_score);
+        assert_close(17.25, stats.average_game_length);
+        assert_close(0.125, stats.first_player_advantage());
+        assert_eq!(Some(&4), stats.end_reasons.values().next());
+
+        let comparison = stats.compare_seats(0, 1);
+        assert_close(1.0 / 3f64.sqrt(), comparison.z);
+    }
+}
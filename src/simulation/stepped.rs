@@ -0,0 +1,513 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt::{Display, Formatter},
+    rc::Rc,
+};
+
+use itertools::Itertools;
+
+use crate::game::{
+    card_registry::CardRegistry,
+    cards::Card,
+    logs::{GameLog, GameLogInner, NullLog, ReplayLog},
+    observation::Observation,
+    players::{Agent2, ChoiceRequest, ChoiceResponse, InvalidChoice},
+    shuffler::{RandomShuffler, Shuffler},
+    snapshot::GameSnapshot,
+    Game,
+};
+
+use super::GameRecord;
+
+/** How far a `SteppedGame` has got */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /** The player in this seat has to answer before the game can carry on */
+    Choice {
+        player: usize,
+        request: ChoiceRequest,
+        observation: Observation,
+    },
+    Finished(GameRecord),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RespondError {
+    /** There's no choice waiting for an answer, so `step` needs calling first */
+    NothingPending,
+    /** The answer doesn't fit the question. The choice is still pending,
+     * so it can be answered again.
+     */
+    Invalid(InvalidChoice),
+}
+
+impl Display for RespondError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RespondError::NothingPending => write!(f, "there's no choice waiting for an answer"),
+            RespondError::Invalid(e) => write!(f, "that's not a valid answer: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for RespondError {}
+
+/** Plays a single game where every decision gets handed back to the caller,
+ * rather than the game asking an `Agent` for it. This means a UI (or something
+ * on the other end of a network connection) can take as long as it likes over
+ * each choice, without needing to be an `Agent` itself.
+ *
+ * Nothing runs in between calls: the game keeps a snapshot of how things were
+ * at the start of the current round, and each `step` plays the round again
+ * from there, answering with the responses so far, until it gets to a question
+ * that hasn't been answered yet. That way the game logic doesn't need to be
+ * able to stop partway through a turn. Given the seed from a simulated game's
+ * `GameRecord` and the same answers, a stepped game turns out the same way as
+ * the simulated one.
+ */
+pub struct SteppedGame<'s> {
+    seed: u64,
+    players: Vec<String>,
+    setup_supply: Box<dyn Fn(&mut Game) + 's>,
+    log: Rc<ReplayLog>,
+    /** Every card in the game, for restoring `round_start` */
+    registry: CardRegistry,
+    /** The game as it was when the current round started, once it's been dealt */
+    round_start: Option<GameSnapshot>,
+    /** Every answer so far this round, in the order the questions were asked */
+    answers: Vec<ChoiceResponse>,
+    /** The choice waiting to be answered, or how the game ended */
+    current: Option<Step>,
+}
+
+impl<'s> SteppedGame<'s> {
+    pub fn new(seed: u64) -> Self {
+        SteppedGame {
+            seed,
+            players: vec![],
+            setup_supply: Box::new(|game| game.populate_basic_kingdom()),
+            log: Rc::new(ReplayLog::new(Rc::new(NullLog::new()))),
+            registry: CardRegistry::new(),
+            round_start: None,
+            answers: vec![],
+            current: None,
+        }
+    }
+
+    fn check_not_started(&self) {
+        assert!(
+            self.round_start.is_none(),
+            "the game can't be changed once it's started"
+        );
+    }
+
+    pub fn add_player(&mut self, name: impl Into<String>) {
+        self.check_not_started();
+        self.players.push(name.into());
+    }
+
+    pub fn set_supply(&mut self, setup_supply: impl Fn(&mut Game) + 's) {
+        self.check_not_started();
+        self.setup_supply = Box::new(setup_supply);
+    }
+
+    /** The log sees everything that happens up to the current choice, just
+     * once, even though each round gets played again for every step
+     */
+    pub fn set_log(&mut self, log: Rc<dyn GameLogInner>) {
+        self.check_not_started();
+        self.log = Rc::new(ReplayLog::new(log));
+    }
+
+    /** Plays until somebody needs to make a choice, or the game ends. Until the
+     * choice gets answered with `respond`, this keeps returning the same one.
+     */
+    pub fn step(&mut self) -> Step {
+        if let Some(current) = &self.current {
+            return current.clone();
+        }
+        if self.round_start.is_none() {
+            self.deal();
+        }
+        let step = self.play_from_round_start();
+        self.current = Some(step.clone());
+        step
+    }
+
+    /** Answers the choice that `step` returned, which lets the game carry on */
+    pub fn respond(&mut self, response: ChoiceResponse) -> Result<(), RespondError> {
+        let Some(Step::Choice { request, .. }) = &self.current else {
+            return Err(RespondError::NothingPending);
+        };
+        request.validate(&response).map_err(RespondError::Invalid)?;
+        self.answers.push(response);
+        self.current = None;
+        Ok(())
+    }
+
+    /** Sets up the supply and deals the starting hands, which nobody has to
+     * make any choices for, so this only happens once
+     */
+    fn deal(&mut self) {
+        let script = Script::new(vec![], &self.log);
+        let shuffler = RandomShuffler::new(self.seed);
+        let mut agents = script.seats(self.players.len());
+        let mut game = Game::new(GameLog::new(self.log.clone()));
+        for (name, agent) in self.players.iter().zip(agents.iter_mut()) {
+            game.add_player(name, agent, &shuffler);
+        }
+        (self.setup_supply)(&mut game);
+        game.deal_starting_hands();
+
+        self.registry = game.card_registry();
+        self.round_start = Some(game.snapshot());
+        self.log.checkpoint();
+    }
+
+    fn play_from_round_start(&mut self) -> Step {
+        let start = self
+            .round_start
+            .clone()
+            .expect("BUG: the game should have been dealt already");
+        self.log.rewind();
+        let script = Script::new(std::mem::take(&mut self.answers), &self.log);
+        // everyone shares one shuffler, so they all saved the same state
+        let shuffler = RandomShuffler::from_state(
+            start.players[0]
+                .shuffler
+                .clone()
+                .expect("BUG: random shufflers can always be saved"),
+        );
+        let mut agents = script.seats(start.players.len());
+        let seats = agents
+            .iter_mut()
+            .map(|agent| (agent as &mut dyn Agent2, &shuffler as &dyn Shuffler<Card>))
+            .collect();
+        let mut game = Game::restore(
+            &start,
+            &self.registry,
+            GameLog::new(self.log.clone()),
+            seats,
+        )
+        .expect("BUG: the registry has every card in the game");
+
+        while !game.is_over() {
+            game.play_rounds(1);
+            if let Some(choice) = script.unanswered.take() {
+                self.answers = script.answers.take();
+                return choice;
+            }
+            // nobody's waiting on anything, so later steps can start from here
+            self.round_start = Some(game.snapshot());
+            self.log.checkpoint();
+            script.start_round();
+        }
+        let results = game.resume_to_end();
+        Step::Finished(GameRecord::new(self.seed, &results))
+    }
+}
+
+/** The answers shared between every seat in one playthrough of a round */
+struct Script<'a> {
+    answers: RefCell<Vec<ChoiceResponse>>,
+    asked: Cell<usize>,
+    /** The first question we didn't have an answer for */
+    unanswered: RefCell<Option<Step>>,
+    log: &'a ReplayLog,
+}
+
+impl<'a> Script<'a> {
+    fn new(answers: Vec<ChoiceResponse>, log: &'a ReplayLog) -> Self {
+        Script {
+            answers: answers.into(),
+            asked: 0.into(),
+            unanswered: None.into(),
+            log,
+        }
+    }
+
+    fn seats(&self, players: usize) -> Vec<ScriptedSeat<'_>> {
+        (0..players)
+            .map(|player| ScriptedSeat {
+                player,
+                script: self,
+            })
+            .collect_vec()
+    }
+
+    /** Every answer so far has been used up by the round that just finished */
+    fn start_round(&self) {
+        self.answers.borrow_mut().clear();
+        self.asked.set(0);
+    }
+}
+
+/** Answers from the script, and then makes do with default answers for the
+ * rest of the round once it runs out (none of which really happens)
+ */
+#[derive(Debug)]
+struct ScriptedSeat<'a> {
+    player: usize,
+    script: &'a Script<'a>,
+}
+
+impl std::fmt::Debug for Script<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Script({}/{})",
+            self.asked.get(),
+            self.answers.borrow().len()
+        )
+    }
+}
+
+impl Agent2 for ScriptedSeat<'_> {
+    fn request_choice(
+        &mut self,
+        request: &ChoiceRequest,
+        observation: &Observation,
+    ) -> ChoiceResponse {
+        let script = self.script;
+        let index = script.asked.get();
+        script.asked.set(index + 1);
+        if let Some(answer) = script.answers.borrow().get(index) {
+            return answer.clone();
+        }
+        let mut unanswered = script.unanswered.borrow_mut();
+        if unanswered.is_none() {
+            script.log.pause();
+            *unanswered = Some(Step::Choice {
+                player: self.player,
+                request: request.clone(),
+                observation: observation.clone(),
+            });
+        }
+        request.question().default_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::{
+            card_registry::CardRegistry, cards::CardNames, logs::tests::TestLog, players::Agents,
+            results::GameEndReason,
+        },
+        simulation::Simulation,
+    };
+
+    fn setup_supply(game: &mut Game) {
+        let registry = CardRegistry::with_builtin_cards();
+        game.populate_basic_kingdom();
+        game.populate_supply_from(&registry, "Witch").unwrap();
+        game.populate_supply_from(&registry, "Moat").unwrap();
+    }
+
+    /** Answers every choice with the agent in that seat */
+    fn play(game: &mut SteppedGame, agents: &mut [Box<dyn Agent2>]) -> GameRecord {
+        loop {
+            match game.step() {
+                Step::Choice {
+                    player,
+                    request,
+                    observation,
+                } => {
+                    let response = agents[player].request_choice(&request, &observation);
+                    game.respond(response).unwrap();
+                }
+                Step::Finished(record) => return record,
+            }
+        }
+    }
+
+    fn agents() -> [Box<dyn Agent2>; 2] {
+        [
+            Box::new(Agents::big_money_witch()),
+            Box::new(Agents::simple_big_money()),
+        ]
+    }
+
+    #[test]
+    fn a_stepped_game_plays_out_the_same_as_a_simulated_one() {
+        let mut simulation = Simulation::new(1, 42);
+        simulation.add_player("P1", || Box::new(Agents::big_money_witch()));
+        simulation.add_player("P2", || Box::new(Agents::simple_big_money()));
+        simulation.set_supply(setup_supply);
+        let simulated = simulation.run().games.remove(0);
+
+        let mut game = SteppedGame::new(simulated.seed);
+        game.add_player("P1");
+        game.add_player("P2");
+        game.set_supply(setup_supply);
+        let stepped = play(&mut game, &mut agents());
+
+        assert_eq!(simulated, stepped);
+        assert_eq!(Step::Finished(stepped), game.step());
+    }
+
+    #[test]
+    fn the_log_sees_everything_once_as_it_happens() {
+        let played_log = Rc::new(TestLog::new());
+        let shuffler = RandomShuffler::new(42);
+        let [mut p1, mut p2] = agents();
+        let mut played = Game::new(GameLog::new(played_log.clone()));
+        played.add_player("P1", p1.as_mut(), &shuffler);
+        played.add_player("P2", p2.as_mut(), &shuffler);
+        setup_supply(&mut played);
+        played.play_to_end();
+
+        let stepped_log = Rc::new(TestLog::new());
+        let mut game = SteppedGame::new(42);
+        game.add_player("P1");
+        game.add_player("P2");
+        game.set_supply(setup_supply);
+        game.set_log(stepped_log.clone());
+        let mut agents = agents();
+        for _ in 0..20 {
+            let Step::Choice {
+                player,
+                request,
+                observation,
+            } = game.step()
+            else {
+                panic!("the game shouldn't be over yet");
+            };
+            let response = agents[player].request_choice(&request, &observation);
+            game.respond(response).unwrap();
+        }
+        let partway = stepped_log.dump();
+        play(&mut game, &mut agents);
+
+        assert_eq!(played_log.dump(), stepped_log.dump());
+        assert!(stepped_log.dump().starts_with(&partway));
+    }
+
+    #[test]
+    fn a_choice_stays_pending_until_its_answered() {
+        let mut game = SteppedGame::new(1);
+        game.add_player("P1");
+
+        let first = game.step();
+        assert!(matches!(first, Step::Choice { player: 0, .. }));
+        assert_eq!(first, game.step());
+
+        game.respond(ChoiceResponse::none()).unwrap();
+        assert_eq!(
+            Err(RespondError::NothingPending),
+            game.respond(ChoiceResponse::none())
+        );
+    }
+
+    #[test]
+    fn invalid_answers_can_be_tried_again() {
+        let mut game = SteppedGame::new(1);
+        game.add_player("P1");
+        let first = game.step();
+
+        let result = game.respond(ChoiceResponse::one(CardNames::PROVINCE));
+
+        assert_eq!(
+            Err(RespondError::Invalid(InvalidChoice::NotAnOption(
+                CardNames::PROVINCE
+            ))),
+            result
+        );
+        assert_eq!(first, game.step());
+    }
+
+    #[test]
+    fn the_supply_only_gets_set_up_once() {
+        let setups = Cell::new(0);
+        let mut game = SteppedGame::new(42);
+        game.add_player("P1");
+        game.add_player("P2");
+        game.set_supply(|game| {
+            setups.set(setups.get() + 1);
+            setup_supply(game);
+        });
+
+        play(&mut game, &mut agents());
+
+        assert_eq!(1, setups.get());
+    }
+
+    /** Passes choices on to another agent, keeping track of what it was asked */
+    #[derive(Debug)]
+    struct Recording {
+        agent: Box<dyn Agent2>,
+        asked: Rc<RefCell<Vec<Step>>>,
+        player: usize,
+    }
+    impl Agent2 for Recording {
+        fn request_choice(
+            &mut self,
+            request: &ChoiceRequest,
+            observation: &Observation,
+        ) -> ChoiceResponse {
+            self.asked.borrow_mut().push(Step::Choice {
+                player: self.player,
+                request: request.clone(),
+                observation: observation.clone(),
+            });
+            self.agent.request_choice(request, observation)
+        }
+    }
+
+    #[test]
+    fn a_long_game_asks_the_same_questions_when_its_stepped() {
+        // nobody buys anything that ends the game, so it goes on to the turn limit
+        let long_game_agents = || -> [Box<dyn Agent2>; 2] {
+            [
+                Box::new(Agents::always_buy_copper()),
+                Box::new(Agents::priorities(vec![CardNames::SILVER], vec![])),
+            ]
+        };
+        let asked = Rc::new(RefCell::new(vec![]));
+        let mut recording = long_game_agents()
+            .into_iter()
+            .enumerate()
+            .map(|(player, agent)| Recording {
+                agent,
+                asked: asked.clone(),
+                player,
+            })
+            .collect_vec();
+        let shuffler = RandomShuffler::new(42);
+        let mut played = Game::new(GameLog::new(Rc::new(NullLog::new())));
+        for (name, agent) in ["P1", "P2"].into_iter().zip(recording.iter_mut()) {
+            played.add_player(name, agent, &shuffler);
+        }
+        played.populate_basic_kingdom();
+        let played_results = GameRecord::new(42, &played.play_to_end());
+
+        let mut game = SteppedGame::new(42);
+        game.add_player("P1");
+        game.add_player("P2");
+        let mut agents = long_game_agents();
+        let mut stepped = vec![];
+        let stepped_results = loop {
+            let step = game.step();
+            match &step {
+                Step::Choice {
+                    player,
+                    request,
+                    observation,
+                } => {
+                    let response = agents[*player].request_choice(request, observation);
+                    stepped.push(step.clone());
+                    game.respond(response).unwrap();
+                }
+                Step::Finished(record) => break record.clone(),
+            }
+        };
+
+        assert_eq!(
+            GameEndReason::TurnLimitReached(100),
+            played_results.end_reason
+        );
+        assert_eq!(*asked.borrow(), stepped);
+        assert_eq!(played_results, stepped_results);
+    }
+}
@@ -0,0 +1,276 @@
+use std::rc::Rc;
+
+use itertools::Itertools;
+
+use crate::game::{
+    logs::GameLogInner,
+    players::{Agent2, Agents},
+    results::PlayerOutcome,
+    Game,
+};
+
+use super::{seed_for_game, AgentFactory, LogFactory, Simulation, SupplySetup};
+
+struct Entrant<'s> {
+    name: String,
+    make_agent: AgentFactory<'s>,
+}
+
+/** Plays every entrant against every other entrant, in both seat orders,
+ * and rates them based on how they did.
+ */
+pub struct Tournament<'s> {
+    entrants: Vec<Entrant<'s>>,
+    setup_supply: SupplySetup<'s>,
+    make_log: Option<LogFactory<'s>>,
+    /** For each seat order, so each pairing plays twice this many games */
+    games_per_pairing: u32,
+    seed: u64,
+    threads: Option<usize>,
+}
+
+impl<'s> Tournament<'s> {
+    pub fn new(games_per_pairing: u32, seed: u64) -> Self {
+        Tournament {
+            entrants: vec![],
+            setup_supply: Box::new(|game| game.populate_basic_kingdom()),
+            make_log: None,
+            games_per_pairing,
+            seed,
+            threads: None,
+        }
+    }
+
+    pub fn add_entrant(
+        &mut self,
+        name: impl Into<String>,
+        make_agent: impl Fn() -> Box<dyn Agent2> + Sync + 's,
+    ) {
+        self.entrants.push(Entrant {
+            name: name.into(),
+            make_agent: Box::new(make_agent),
+        });
+    }
+
+    /** Enters everything `Agents::by_name` knows about */
+    pub fn add_builtin_agents(&mut self) {
+        for &name in Agents::NAMES {
+            self.add_entrant(name, move || {
+                Agents::by_name(name).expect("BUG: Agents::NAMES should all be makeable")
+            });
+        }
+    }
+
+    pub fn set_supply(&mut self, setup_supply: impl Fn(&mut Game) + Sync + 's) {
+        self.setup_supply = Box::new(setup_supply);
+    }
+
+    pub fn set_log(&mut self, make_log: impl Fn(u64) -> Rc<dyn GameLogInner> + Sync + 's) {
+        self.make_log = Some(Box::new(make_log));
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = Some(threads);
+    }
+
+    /** Every pair of entrants, in both seat orders */
+    fn schedule(&self) -> Vec<(usize, usize)> {
+        (0..self.entrants.len())
+            .tuple_combinations()
+            .flat_map(|(a, b)| [(a, b), (b, a)])
+            .collect()
+    }
+
+    pub fn run(&self) -> TournamentResults {
+        let n = self.entrants.len();
+        let mut games = vec![vec![0; n]; n];
+        let mut points = vec![vec![0.0; n]; n];
+
+        for (index, (first, second)) in self.schedule().into_iter().enumerate() {
+            let mut simulation = Simulation::new(
+                self.games_per_pairing,
+                seed_for_game(self.seed, index as u32),
+            );
+            for &seat in &[first, second] {
+                let entrant = &self.entrants[seat];
+                simulation.add_player(entrant.name.clone(), || (entrant.make_agent)());
+            }
+            simulation.set_supply(|game| (self.setup_supply)(game));
+            if let Some(make_log) = &self.make_log {
+                simulation.set_log(make_log);
+            }
+            if let Some(threads) = self.threads {
+                simulation.set_threads(threads);
+            }
+
+            let results = simulation.run();
+            for game in &results.games {
+                for (me, them, record) in [
+                    (first, second, &game.players[0]),
+                    (second, first, &game.players[1]),
+                ] {
+                    games[me][them] += 1;
+                    points[me][them] += match record.outcome {
+                        PlayerOutcome::Win => 1.0,
+                        PlayerOutcome::SharedWin => 0.5,
+                        PlayerOutcome::Loss => 0.0,
+                    };
+                }
+            }
+        }
+
+        TournamentResults {
+            names: self.entrants.iter().map(|e| e.name.clone()).collect(),
+            ratings: bradley_terry_ratings(&points, &games),
+            games,
+            points,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TournamentResults {
+    pub names: Vec<String>,
+    /** `games[a][b]` is how many games `a` played against `b` */
+    pub games: Vec<Vec<u32>>,
+    /** `points[a][b]` is how many games `a` won against `b`, with shared wins counting half */
+    pub points: Vec<Vec<f64>>,
+    /** On the Elo scale, averaging 1500 */
+    pub ratings: Vec<f64>,
+}
+
+impl TournamentResults {
+    /** Entrant indexes, best first */
+    pub fn ranking(&self) -> Vec<usize> {
+        (0..self.names.len())
+            .sorted_by(|&a, &b| self.ratings[b].total_cmp(&self.ratings[a]))
+            .collect()
+    }
+
+    pub fn total_games(&self, entrant: usize) -> u32 {
+        self.games[entrant].iter().sum()
+    }
+
+    pub fn total_points(&self, entrant: usize) -> f64 {
+        self.points[entrant].iter().sum()
+    }
+
+    /** How often `a` beat `b`, if they played at all */
+    pub fn win_rate(&self, a: usize, b: usize) -> Option<f64> {
+        match self.games[a][b] {
+            0 => None,
+            games => Some(self.points[a][b] / f64::from(games)),
+        }
+    }
+}
+
+/** Fits a Bradley-Terry model (the maximum likelihood version of Elo, so the
+ * order games were played in doesn't matter) and puts it on the Elo scale.
+ *
+ * Each pairing gets one extra drawn game, so that an entrant who never won
+ * (or never lost) still ends up with a finite rating.
+ */
+pub fn bradley_terry_ratings(points: &[Vec<f64>], games: &[Vec<u32>]) -> Vec<f64> {
+    let n = points.len();
+    let played = |a: usize, b: usize| games[a][b] > 0 || games[b][a] > 0;
+    let wins = (0..n)
+        .map(|a| {
+            (0..n)
+                .filter(|&b| b != a && played(a, b))
+                .map(|b| points[a][b] + 0.5)
+                .sum::<f64>()
+        })
+        .collect_vec();
+
+    let mut strength = vec![1.0; n];
+    for _ in 0..1000 {
+        let mut next = (0..n)
+            .map(|a| {
+                let expected: f64 = (0..n)
+                    .filter(|&b| b != a && played(a, b))
+                    .map(|b| f64::from(games[a][b] + 1) / (strength[a] + strength[b]))
+                    .sum();
+                if expected == 0.0 {
+                    1.0
+                } else {
+                    wins[a] / expected
+                }
+            })
+            .collect_vec();
+        // only the ratios matter, so keep the geometric mean at 1
+        let log_mean = next.iter().map(|s: &f64| s.ln()).sum::<f64>() / n.max(1) as f64;
+        next.iter_mut().for_each(|s| *s /= log_mean.exp());
+
+        let converged = next
+            .iter()
+            .zip(&strength)
+            .all(|(new, old)| (new - old).abs() < 1e-10);
+        strength = next;
+        if converged {
+            break;
+        }
+    }
+
+    strength
+        .iter()
+        .map(|s| 1500.0 + 400.0 * s.log10())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_pairing_plays_both_seat_orders() {
+        let mut tournament = Tournament::new(3, 1234);
+        tournament.add_entrant("SBM", || Box::new(Agents::simple_big_money()));
+        tournament.add_entrant("BMW", || Box::new(Agents::big_money_witch()));
+        tournament.add_entrant("Copper", || Box::new(Agents::always_buy_copper()));
+        tournament.set_threads(2);
+
+        let results = tournament.run();
+
+        for (a, b) in (0..3).tuple_combinations() {
+            assert_eq!(6, results.games[a][b]);
+            assert_eq!(6, results.games[b][a]);
+            assert_eq!(6.0, results.points[a][b] + results.points[b][a]);
+        }
+        assert_eq!(0, results.games[1][1]);
+        assert_eq!(Some(&2), results.ranking().last());
+    }
+
+    #[test]
+    fn tournaments_are_reproducible() {
+        let run = || {
+            let mut tournament = Tournament::new(2, 99);
+            tournament.add_builtin_agents();
+            tournament.run()
+        };
+
+        assert_eq!(run().points, run().points);
+    }
+
+    #[test]
+    fn ratings_reflect_how_often_each_entrant_wins() {
+        // A beats B 3 times out of 4, which is about 190 Elo points
+        let points = vec![vec![0.0, 3000.0], vec![1000.0, 0.0]];
+        let games = vec![vec![0, 4000], vec![4000, 0]];
+
+        let ratings = bradley_terry_ratings(&points, &games);
+
+        assert!((ratings[0] - ratings[1] - 190.8).abs() < 0.5);
+        assert!((ratings[0] + ratings[1] - 3000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn entrants_who_never_win_still_get_a_rating() {
+        let points = vec![vec![0.0, 10.0], vec![0.0, 0.0]];
+        let games = vec![vec![0, 10], vec![10, 0]];
+
+        let ratings = bradley_terry_ratings(&points, &games);
+
+        assert!(ratings.iter().all(|r| r.is_finite()));
+        assert!(ratings[0] > ratings[1]);
+    }
+}